@@ -0,0 +1,403 @@
+use artwork;
+use clap;
+use number;
+use tag;
+
+use regex::Regex;
+use rtag::frame::{FrameBody, id};
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use {All, ViewFrame, load, update};
+
+//
+// Frames that should be the same in all files of an album.
+//
+const UNIFORM: [&str; 4] = [id::TALB, id::TPE2, id::TDRC, id::TCON];
+
+//
+// "none" if a file has no artwork.
+//
+const NO_ARTWORK: &str = "none";
+
+struct Track {
+    path: PathBuf,
+    all: All,
+    //
+    // a value by frame id of `UNIFORM`. empty if there is none.
+    //
+    values: BTreeMap<&'static str, String>,
+    //
+    // md5 of the first picture and its frame
+    //
+    artwork: (String, Option<FrameBody>),
+}
+
+impl Track {
+    fn new(path: PathBuf) -> Option<Track> {
+        let all = load(&path)?;
+        let texts = tag::texts(&path).unwrap_or_default();
+
+        let values = UNIFORM.iter()
+            .map(|id| (*id, texts.get(*id).map(|v| v.trim().to_string()).unwrap_or_default()))
+            .collect();
+
+        let artwork = all.frames
+            .iter()
+            .flatten()
+            .find_map(|vf| artwork::Picture::from_framebody(&vf.body).map(|p| (p.hash, Some(vf.body.clone()))))
+            .unwrap_or((NO_ARTWORK.to_string(), None));
+
+        Some(Track {
+            path,
+            all,
+            values,
+            artwork,
+        })
+    }
+
+    fn name(&self) -> String {
+        self.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    fn version(&self) -> u8 {
+        match self.all.head {
+            Some(ref head) => head.version.parse().unwrap_or(4),
+            None => 4,
+        }
+    }
+}
+
+//
+// Values with files in order of count, and the first seen for a tie.
+//
+fn variants<'a, F>(tracks: &'a [Track], value: F) -> Vec<(&'a str, Vec<String>)>
+    where F: Fn(&'a Track) -> &'a str
+{
+    let mut variants: Vec<(&str, Vec<String>)> = Vec::new();
+
+    for track in tracks {
+        let value = value(track);
+        match variants.iter().position(|v| v.0 == value) {
+            Some(i) => variants[i].1.push(track.name()),
+            None => variants.push((value, vec![track.name()])),
+        }
+    }
+
+    variants.sort_by_key(|v| Reverse(v.1.len()));
+    variants
+}
+
+//
+// "value" x9, "other" x1 (03.mp3)
+//
+fn describe(variants: &[(&str, Vec<String>)]) -> String {
+    variants.iter()
+        .enumerate()
+        .map(|(i, &(value, ref files))| if i == 0 {
+            format!("{:?} x{}", value, files.len())
+        } else {
+            format!("{:?} x{} ({})", value, files.len(), files.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+//
+// The most common value that is not empty.
+//
+fn majority<'a>(variants: &[(&'a str, Vec<String>)], empty: &str) -> Option<&'a str> {
+    variants.iter().map(|v| v.0).find(|value| *value != empty)
+}
+
+fn replace<F>(all: &mut All, fbody: FrameBody, same: F)
+    where F: Fn(&FrameBody) -> bool
+{
+    let frames = all.frames.get_or_insert_with(Vec::new);
+
+    match frames.iter().position(|vf| same(&vf.body)) {
+        Some(i) => frames[i].body = fbody,
+        None => {
+            frames.push(ViewFrame {
+                flags: None,
+                body: fbody,
+                picture: None,
+                resource: None,
+                genre: None,
+            })
+        }
+    }
+}
+
+//
+// A report of an album, and changes by majority to files if `fix`.
+//
+fn check(tracks: &mut [Track], fix: bool, number: &Regex) -> (Vec<String>, BTreeMap<usize, Vec<String>>) {
+    let mut report = Vec::new();
+    let mut fixes: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+
+    for id in UNIFORM.iter() {
+        let value = {
+            let variants = variants(tracks, |track| track.values[id].as_str());
+            if variants.len() < 2 {
+                continue;
+            }
+
+            report.push(format!("{}: {}", id, describe(&variants)));
+            majority(&variants, "").map(|v| v.to_string())
+        };
+
+        let value = match value {
+            Some(ref value) if fix => value,
+            _ => continue,
+        };
+
+        for (i, track) in tracks.iter_mut().enumerate() {
+            if track.values[id] == *value {
+                continue;
+            }
+
+            let fbody = match tag::text_frame(id, value, track.version()) {
+                Some(fbody) => fbody,
+                None => continue,
+            };
+
+            fixes.entry(i).or_default().push(format!("{}: {:?} => {:?}", id, track.values[id], value));
+
+            let key = tag::text_of(&fbody).map(|(key, _)| key);
+            replace(&mut track.all,
+                    fbody,
+                    |body| tag::text_of(body).map(|(k, _)| k) == key);
+        }
+    }
+
+    let artwork = {
+        let variants = variants(tracks, |track| track.artwork.0.as_str());
+        if variants.len() > 1 {
+            report.push(format!("artwork: {}", describe(&variants)));
+        }
+        majority(&variants, NO_ARTWORK).map(|hash| hash.to_string()).filter(|_| variants.len() > 1)
+    };
+
+    if let (true, Some(hash)) = (fix, artwork) {
+        let picture = tracks.iter().find(|t| t.artwork.0 == hash).and_then(|t| t.artwork.1.clone());
+
+        if let Some(picture) = picture {
+            //
+            // a track without artwork is kept as is. only a different picture is replaced.
+            //
+            for (i, track) in tracks.iter_mut().enumerate() {
+                if track.artwork.0 == hash || track.artwork.0 == NO_ARTWORK {
+                    continue;
+                }
+
+                fixes.entry(i).or_default().push(format!("artwork: {} => {}", track.artwork.0, hash));
+                replace(&mut track.all, picture.clone(), |body| {
+                    artwork::Picture::from_framebody(body).is_some()
+                });
+            }
+        }
+    }
+
+    let paths: Vec<PathBuf> = tracks.iter().map(|track| track.path.clone()).collect();
+    report.extend(number::Album::new(&paths, number).report());
+
+    (report, fixes)
+}
+
+//
+// An album of a file. a directory, or TALB and TPE2.
+//
+fn album_of(path: &Path, by: &str) -> String {
+    match by {
+        "tag" => {
+            let texts = tag::texts(path).unwrap_or_default();
+            let text = |id: &str| texts.get(id).cloned().unwrap_or_default();
+            format!("{} / {}", text(id::TALB), text(id::TPE2))
+        }
+        _ => path.parent().map(|dir| dir.display().to_string()).unwrap_or_default(),
+    }
+}
+
+pub fn check_album(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+    let fix = matches.is_present("fix-album");
+    let by = matches.value_of("check-album").unwrap();
+
+    match by {
+        "dir" | "tag" => (),
+        _ => {
+            error!("Unknown check-album: {}. (dir|tag)", by);
+            return;
+        }
+    }
+
+    let number = Regex::new(number::NUMBER).unwrap();
+    let mut albums: BTreeMap<String, Vec<Track>> = BTreeMap::new();
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let album = album_of(&path, by);
+        albums.entry(album).or_default().extend(Track::new(path));
+    }
+
+    for (album, mut tracks) in albums {
+        let (report, fixes) = check(&mut tracks, fix, &number);
+
+        if report.is_empty() {
+            debug!("consistent album: {}", album);
+            continue;
+        }
+
+        println!("{}", album);
+        for line in report {
+            println!("\t{}", line);
+        }
+
+        for (i, lines) in fixes {
+            let track = &mut tracks[i];
+
+            println!("\tfix {}", track.name());
+            for line in lines {
+                println!("\t\t{}", line);
+            }
+
+            if dry_run {
+                continue;
+            }
+
+            if track.all.head.is_none() {
+                track.all.head = Some(::ViewHead {
+                    version: "4".to_string(),
+                    flags: None,
+                });
+            }
+
+            if let Err(e) = update(&mut track.all, &artwork::Shrink::default(), Path::new("")) {
+                error!("Can not write: {:?}, {:?}", track.path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtag::frame::APIC;
+    use rtag::frame::types::{PictureType, TextEncoding};
+
+    fn track(name: &str, album: &str, picture: Option<&[u8]>) -> Track {
+        let frames: Vec<ViewFrame> = picture.iter()
+            .map(|data| {
+                ViewFrame {
+                    flags: None,
+                    body: FrameBody::APIC(APIC {
+                        text_encoding: TextEncoding::ISO88591,
+                        mime_type: "image/png".to_string(),
+                        picture_type: PictureType::CoverFront,
+                        description: String::new(),
+                        picture_data: data.to_vec(),
+                    }),
+                    picture: None,
+                    resource: None,
+                    genre: None,
+                }
+            })
+            .collect();
+
+        let artwork = match picture {
+            Some(data) => (format!("{:x}", ::md5::compute(data)), frames.first().map(|vf| vf.body.clone())),
+            None => (NO_ARTWORK.to_string(), None),
+        };
+
+        Track {
+            path: PathBuf::from(format!("/nonexistent/{}", name)),
+            all: All {
+                file: name.to_string(),
+                head: None,
+                frames: Some(frames),
+                frame1: None,
+                audio_md5: None,
+            },
+            values: UNIFORM.iter()
+                .map(|id| (*id, if *id == id::TALB { album.to_string() } else { String::new() }))
+                .collect(),
+            artwork,
+        }
+    }
+
+    fn albums(tracks: &[Track]) -> Vec<(&str, Vec<String>)> {
+        variants(tracks, |track| track.values[id::TALB].as_str())
+    }
+
+    #[test]
+    fn variants_and_majority() {
+        let tracks = vec![track("01.mp3", "", None),
+                          track("02.mp3", "B", None),
+                          track("03.mp3", "", None),
+                          track("04.mp3", "A", None),
+                          track("05.mp3", "A", None),
+                          track("06.mp3", "", None)];
+
+        let variants = albums(&tracks);
+        assert_eq!(variants,
+                   vec![("", vec!["01.mp3".to_string(), "03.mp3".to_string(), "06.mp3".to_string()]),
+                        ("A", vec!["04.mp3".to_string(), "05.mp3".to_string()]),
+                        ("B", vec!["02.mp3".to_string()])]);
+        assert_eq!(majority(&variants, ""), Some("A"));
+        assert_eq!(describe(&variants), r#""" x3, "A" x2 (04.mp3, 05.mp3), "B" x1 (02.mp3)"#);
+
+        //
+        // the first seen for a tie
+        //
+        let tracks = vec![track("01.mp3", "B", None), track("02.mp3", "A", None)];
+        assert_eq!(majority(&albums(&tracks), ""), Some("B"));
+
+        let tracks = vec![track("01.mp3", "", None)];
+        assert_eq!(majority(&albums(&tracks), ""), None);
+    }
+
+    #[test]
+    fn fix_by_majority() {
+        let mut tracks = vec![track("01.mp3", "A", Some(b"cover")),
+                              track("02.mp3", "A", Some(b"cover")),
+                              track("03.mp3", "", Some(b"other")),
+                              track("04.mp3", "A", None)];
+
+        let regex = Regex::new(number::NUMBER).unwrap();
+        let (report, fixes) = check(&mut tracks, true, &regex);
+
+        assert_eq!(report.len(), 2);
+        assert!(report[0].starts_with("TALB: "));
+        assert!(report[1].starts_with("artwork: "));
+
+        let cover = format!("{:x}", ::md5::compute(b"cover"));
+        let other = format!("{:x}", ::md5::compute(b"other"));
+
+        assert_eq!(fixes.keys().cloned().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(fixes[&2],
+                   vec![r#"TALB: "" => "A""#.to_string(), format!("artwork: {} => {}", other, cover)]);
+
+        let pictures = |track: &Track| {
+            track.all
+                .frames
+                .iter()
+                .flatten()
+                .filter_map(|vf| artwork::Picture::from_framebody(&vf.body))
+                .map(|picture| picture.hash)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pictures(&tracks[2]), vec![cover]);
+        assert!(pictures(&tracks[3]).is_empty());
+    }
+}
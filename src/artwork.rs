@@ -0,0 +1,432 @@
+use image;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use image::imageops::FilterType;
+use md5;
+use rtag::frame::{APIC, FrameBody, PIC};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+
+//
+// Limits that a picture placed into `picture_data` must fit in.
+// the source image on disk is never touched, only the embedded bytes are re-encoded.
+//
+#[derive(Debug, Clone, Default)]
+pub struct Shrink {
+    pub max_dimension: Option<u32>,
+    pub max_bytes: Option<usize>,
+}
+
+impl Shrink {
+    pub fn is_empty(&self) -> bool {
+        self.max_dimension.is_none() && self.max_bytes.is_none()
+    }
+}
+
+const JPEG_QUALITIES: [u8; 6] = [90, 85, 75, 65, 55, 45];
+const MIN_DIMENSION: u32 = 64;
+
+//
+// It returns re-encoded bytes, or None if the picture already fits.
+// a PNG or a picture with transparency is kept as PNG, and others are re-encoded as jpeg.
+//
+pub fn shrink(bytes: &[u8], limit: &Shrink) -> Option<Vec<u8>> {
+    if limit.is_empty() {
+        return None;
+    }
+
+    let img = match image::load_from_memory(bytes) {
+        Ok(img) => img,
+        Err(e) => {
+            warn!("Can not decode a picture, it is embedded as is: {:?}", e);
+            return None;
+        }
+    };
+
+    let (width, height) = img.dimensions();
+    let too_large = match limit.max_dimension {
+        Some(max) => width > max || height > max,
+        None => false,
+    };
+    let too_heavy = match limit.max_bytes {
+        Some(max) => bytes.len() > max,
+        None => false,
+    };
+
+    if !too_large && !too_heavy {
+        return None;
+    }
+
+    let png = image::guess_format(bytes).ok() == Some(ImageFormat::Png) || img.color().has_alpha();
+
+    let mut img = match limit.max_dimension {
+        Some(max) if too_large => img.resize(max, max, FilterType::Lanczos3),
+        _ => img,
+    };
+
+    let fits = |buf: &Vec<u8>| match limit.max_bytes {
+        Some(max) => buf.len() <= max,
+        None => true,
+    };
+
+    loop {
+        let buf = if png {
+            to_png(&img)?
+        } else {
+            let mut encoded = None;
+
+            for quality in JPEG_QUALITIES.iter() {
+                let buf = to_jpeg(&img, *quality)?;
+                let fit = fits(&buf);
+
+                encoded = Some(buf);
+
+                if fit {
+                    break;
+                }
+            }
+
+            encoded.unwrap()
+        };
+
+        let (w, h) = img.dimensions();
+        let fit = fits(&buf);
+
+        if fit || w <= MIN_DIMENSION || h <= MIN_DIMENSION {
+            debug!("shrink picture: {}x{} {} bytes => {}x{} {} bytes",
+                   width,
+                   height,
+                   bytes.len(),
+                   w,
+                   h,
+                   buf.len());
+
+            if !fit {
+                warn!("Can not shrink a picture under {} bytes", limit.max_bytes.unwrap());
+            }
+
+            return Some(buf);
+        }
+
+        img = img.resize(w * 3 / 4, h * 3 / 4, FilterType::Lanczos3);
+    }
+}
+
+//
+// It makes `mime_type`, `image_format` to follow the embedded picture.
+//
+pub fn fix_format(frame_body: &mut FrameBody) {
+    match *frame_body {
+        FrameBody::APIC(ref mut frame) => {
+            if let Some(mime_type) = mime_type(&frame.picture_data) {
+                frame.mime_type = mime_type.to_string();
+            }
+        }
+        FrameBody::PIC(ref mut frame) => {
+            if let Some(image_format) = image_format(&frame.picture_data) {
+                frame.image_format = image_format.to_string();
+            }
+        }
+        _ => (),
+    }
+}
+
+pub fn mime_type(bytes: &[u8]) -> Option<&'static str> {
+    match image::guess_format(bytes) {
+        Ok(image::ImageFormat::Jpeg) => Some("image/jpeg"),
+        Ok(image::ImageFormat::Png) => Some("image/png"),
+        Ok(image::ImageFormat::Gif) => Some("image/gif"),
+        Ok(image::ImageFormat::Bmp) => Some("image/bmp"),
+        _ => None,
+    }
+}
+
+pub fn image_format(bytes: &[u8]) -> Option<&'static str> {
+    match image::guess_format(bytes) {
+        Ok(image::ImageFormat::Jpeg) => Some("JPG"),
+        Ok(image::ImageFormat::Png) => Some("PNG"),
+        Ok(image::ImageFormat::Gif) => Some("GIF"),
+        Ok(image::ImageFormat::Bmp) => Some("BMP"),
+        _ => None,
+    }
+}
+
+//
+// PIC is only for 2.2. `image_format` is 3 characters. ex) "JPG", "PNG"
+//
+pub fn pic_to_apic(frame_body: FrameBody) -> FrameBody {
+    match frame_body {
+        FrameBody::PIC(pic) => {
+            let mime_type = match mime_type(&pic.picture_data) {
+                Some(mime_type) => mime_type.to_string(),
+                None => {
+                    match pic.image_format.to_uppercase().as_str() {
+                        "JPG" | "JPEG" => "image/jpeg".to_string(),
+                        "" => "image/".to_string(),
+                        format => format!("image/{}", format.to_lowercase()),
+                    }
+                }
+            };
+
+            FrameBody::APIC(APIC {
+                text_encoding: pic.text_encoding,
+                mime_type,
+                picture_type: pic.picture_type,
+                description: pic.description,
+                picture_data: pic.picture_data,
+            })
+        }
+        _ => frame_body,
+    }
+}
+
+//
+// APIC is for 2.3 and 2.4. "image/jpeg" => "JPG"
+//
+pub fn apic_to_pic(frame_body: FrameBody) -> FrameBody {
+    match frame_body {
+        FrameBody::APIC(apic) => {
+            let image_format = match image_format(&apic.picture_data) {
+                Some(image_format) => image_format.to_string(),
+                None => {
+                    match apic.mime_type.to_lowercase().trim_start_matches("image/") {
+                        "jpeg" | "jpg" => "JPG".to_string(),
+                        format => format.chars().take(3).collect::<String>().to_uppercase(),
+                    }
+                }
+            };
+
+            FrameBody::PIC(PIC {
+                text_encoding: apic.text_encoding,
+                image_format,
+                picture_type: apic.picture_type,
+                description: apic.description,
+                picture_data: apic.picture_data,
+            })
+        }
+        _ => frame_body,
+    }
+}
+
+fn to_png(img: &DynamicImage) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    match img.write_to(&mut buf, ImageOutputFormat::Png) {
+        Ok(_) => Some(buf),
+        Err(e) => {
+            error!("Can not encode a picture: {:?}", e);
+            None
+        }
+    }
+}
+
+fn to_jpeg(img: &DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    match DynamicImage::ImageRgb8(img.to_rgb8()).write_to(&mut buf, ImageOutputFormat::Jpeg(quality)) {
+        Ok(_) => Some(buf),
+        Err(e) => {
+            error!("Can not encode a picture: {:?}", e);
+            None
+        }
+    }
+}
+
+//
+// What the embedded picture is. `picture_data` is emptied on read, so this is the only hint of it.
+//
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Picture {
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    pub hash: String,
+}
+
+impl Picture {
+    pub fn new(bytes: &[u8]) -> Picture {
+        let format = match image::guess_format(bytes) {
+            Ok(format) => format!("{:?}", format).to_lowercase(),
+            Err(_) => "unknown".to_string(),
+        };
+
+        let reader = image::io::Reader::new(Cursor::new(bytes)).with_guessed_format();
+        let (width, height) = match reader.map(|reader| reader.into_dimensions()) {
+            Ok(Ok(dimensions)) => dimensions,
+            _ => (0, 0),
+        };
+
+        Picture {
+            size: bytes.len(),
+            width,
+            height,
+            format,
+            hash: format!("{:x}", md5::compute(bytes)),
+        }
+    }
+
+    pub fn from_framebody(fbody: &FrameBody) -> Option<Picture> {
+        match *fbody {
+            FrameBody::PIC(ref body) => Some(Picture::new(&body.picture_data)),
+            FrameBody::APIC(ref body) => Some(Picture::new(&body.picture_data)),
+            _ => None,
+        }
+    }
+
+    pub fn extend(&self, map: &mut HashMap<&str, String>) {
+        map.insert("size", self.size.to_string());
+        map.insert("width", self.width.to_string());
+        map.insert("height", self.height.to_string());
+        map.insert("format", self.format.clone());
+        map.insert("hash", self.hash.clone());
+    }
+}
+
+impl fmt::Display for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} bytes, {}x{}, {}, md5 {}",
+               self.size,
+               self.width,
+               self.height,
+               self.format,
+               self.hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+    use rtag::frame::types::{PictureType, TextEncoding};
+
+    //
+    // A picture with noise, that is hard to compress.
+    //
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbImage::from_fn(width, height, |x, y| {
+            let v = (x * 7919 + y * 104729) ^ (x * y);
+            Rgb([v as u8, (v >> 8) as u8, (v >> 16) as u8])
+        });
+        to_jpeg(&DynamicImage::ImageRgb8(img), 95).unwrap()
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 0, (x + y) as u8]));
+        to_png(&DynamicImage::ImageRgba8(img)).unwrap()
+    }
+
+    fn apic(mime_type: &str, picture_data: Vec<u8>) -> FrameBody {
+        FrameBody::APIC(APIC {
+            text_encoding: TextEncoding::ISO88591,
+            mime_type: mime_type.to_string(),
+            picture_type: PictureType::CoverFront,
+            description: String::new(),
+            picture_data,
+        })
+    }
+
+    #[test]
+    fn shrink_dimension() {
+        let limit = Shrink {
+            max_dimension: Some(100),
+            max_bytes: None,
+        };
+
+        let shrunk = shrink(&jpeg(400, 300), &limit).unwrap();
+        let img = image::load_from_memory(&shrunk).unwrap();
+        assert_eq!(img.dimensions(), (100, 75));
+        assert_eq!(mime_type(&shrunk), Some("image/jpeg"));
+
+        assert_eq!(shrink(&jpeg(100, 50), &limit), None);
+        assert_eq!(shrink(&jpeg(400, 300), &Shrink::default()), None);
+    }
+
+    #[test]
+    fn shrink_bytes() {
+        let bytes = jpeg(512, 512);
+        let limit = Shrink {
+            max_dimension: None,
+            max_bytes: Some(bytes.len() / 10),
+        };
+
+        let shrunk = shrink(&bytes, &limit).unwrap();
+        assert!(shrunk.len() <= bytes.len() / 10);
+    }
+
+    #[test]
+    fn shrink_keeps_png() {
+        let limit = Shrink {
+            max_dimension: Some(50),
+            max_bytes: None,
+        };
+
+        let shrunk = shrink(&png(200, 100), &limit).unwrap();
+        let img = image::load_from_memory(&shrunk).unwrap();
+        assert_eq!(mime_type(&shrunk), Some("image/png"));
+        assert_eq!(img.dimensions(), (50, 25));
+        assert!(img.color().has_alpha());
+    }
+
+    #[test]
+    fn fix_mime_type_and_image_format() {
+        let mut fbody = apic("image/jpeg", png(10, 10));
+        fix_format(&mut fbody);
+        match fbody {
+            FrameBody::APIC(ref frame) => assert_eq!(frame.mime_type, "image/png"),
+            _ => unreachable!(),
+        }
+
+        let mut fbody = apic_to_pic(apic("image/png", jpeg(10, 10)));
+        match fbody {
+            FrameBody::PIC(ref frame) => assert_eq!(frame.image_format, "JPG"),
+            _ => unreachable!(),
+        }
+
+        if let FrameBody::PIC(ref mut frame) = fbody {
+            frame.picture_data = png(10, 10);
+        }
+        fix_format(&mut fbody);
+        match fbody {
+            FrameBody::PIC(ref frame) => assert_eq!(frame.image_format, "PNG"),
+            _ => unreachable!(),
+        }
+
+        match pic_to_apic(fbody) {
+            FrameBody::APIC(ref frame) => assert_eq!(frame.mime_type, "image/png"),
+            _ => unreachable!(),
+        }
+
+        let mut unknown = apic("image/webp", b"not a picture".to_vec());
+        fix_format(&mut unknown);
+        match unknown {
+            FrameBody::APIC(ref frame) => assert_eq!(frame.mime_type, "image/webp"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn picture_properties() {
+        let bytes = png(30, 20);
+        let picture = Picture::from_framebody(&apic("image/png", bytes.clone())).unwrap();
+        assert_eq!(picture.size, bytes.len());
+        assert_eq!((picture.width, picture.height), (30, 20));
+        assert_eq!(picture.format, "png");
+        assert_eq!(picture.hash, format!("{:x}", md5::compute(&bytes)));
+
+        let picture = Picture::from_framebody(&apic_to_pic(apic("image/jpeg", jpeg(8, 4)))).unwrap();
+        assert_eq!((picture.width, picture.height), (8, 4));
+        assert_eq!(picture.format, "jpeg");
+
+        let picture = Picture::new(b"not a picture");
+        assert_eq!((picture.width, picture.height), (0, 0));
+        assert_eq!(picture.format, "unknown");
+
+        let mut map = HashMap::new();
+        Picture::new(&[]).extend(&mut map);
+        assert_eq!(map.get("size").map(String::as_str), Some("0"));
+        assert_eq!(map.get("hash").map(String::as_str), Some("d41d8cd98f00b204e9800998ecf8427e"));
+    }
+}
@@ -0,0 +1,545 @@
+use std::fs;
+use std::path::Path;
+
+//
+// Kbps by (MPEG version 1 or not, layer), and the index of a frame header.
+//
+const BITRATES_V1: [[u32; 15]; 3] =
+    [[0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+     [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+     [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320]];
+const BITRATES_V2: [[u32; 15]; 3] =
+    [[0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+     [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+     [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160]];
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+//
+// A header of a MPEG audio frame.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    //
+    // 1, 2 or 25 for MPEG 2.5
+    //
+    pub version: u8,
+    pub layer: u8,
+    pub crc: bool,
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub padding: bool,
+    pub channel_mode: u8,
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8]) -> Option<Header> {
+        if bytes.len() < 4 || bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        let version = match (bytes[1] >> 3) & 0x03 {
+            0 => 25,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+        let layer = match (bytes[1] >> 1) & 0x03 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let sample_rate_index = ((bytes[2] >> 2) & 0x03) as usize;
+
+        //
+        // 0 is a free format that is not supported. 15 is invalid.
+        //
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+
+        let bitrates = if version == 1 { &BITRATES_V1 } else { &BITRATES_V2 };
+        let divisor = match version {
+            1 => 1,
+            2 => 2,
+            _ => 4,
+        };
+
+        Some(Header {
+            version,
+            layer,
+            crc: bytes[1] & 0x01 == 0,
+            bitrate: bitrates[layer as usize - 1][bitrate_index],
+            sample_rate: SAMPLE_RATES[sample_rate_index] / divisor,
+            padding: (bytes[2] >> 1) & 0x01 == 1,
+            channel_mode: bytes[3] >> 6,
+        })
+    }
+
+    pub fn samples(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (2, _) | (3, 1) => 1152,
+            _ => 576,
+        }
+    }
+
+    //
+    // Bytes of a frame with the header.
+    //
+    pub fn frame_len(&self) -> usize {
+        let slot = if self.layer == 1 { 4 } else { 1 };
+        let slots = self.samples() / 8 / slot * self.bitrate * 1000 / self.sample_rate;
+
+        ((slots + if self.padding { 1 } else { 0 }) * slot) as usize
+    }
+
+    //
+    // Headers of frames in a stream must have the same version, layer and sample rate.
+    //
+    pub fn is_same_stream(&self, other: &Header) -> bool {
+        self.version == other.version && self.layer == other.layer &&
+        self.sample_rate == other.sample_rate
+    }
+}
+
+//
+// The end of an ID3v2 tag. a tag can be followed by another, or by zero padding.
+//
+fn id3v2_end(bytes: &[u8], mut start: usize) -> usize {
+    while bytes.len() >= start + 10 && &bytes[start..start + 3] == b"ID3" {
+        let size = bytes[start + 6..start + 10]
+            .iter()
+            .fold(0usize, |size, b| (size << 7) | (*b as usize & 0x7f));
+        let footer = if bytes[start + 5] & 0x10 != 0 { 10 } else { 0 };
+
+        start = (start + 10 + size + footer).min(bytes.len());
+    }
+
+    while start < bytes.len() && bytes[start] == 0 {
+        start += 1;
+    }
+
+    start
+}
+
+//
+// The start of an ID3v1, APEv2 or Lyrics3 tag at the end, if there is.
+// (name, start)
+//
+fn trailing_tag(bytes: &[u8], end: usize) -> Option<(&'static str, usize)> {
+    let tail = &bytes[..end];
+
+    if tail.len() >= 128 && &tail[end - 128..end - 125] == b"TAG" {
+        //
+        // an enhanced tag "TAG+" is in front of a ID3v1 tag.
+        //
+        if end >= 355 && &tail[end - 355..end - 351] == b"TAG+" {
+            return Some(("ID3v1", end - 355));
+        }
+        return Some(("ID3v1", end - 128));
+    }
+
+    if tail.len() >= 32 && &tail[end - 32..end - 24] == b"APETAGEX" {
+        let footer = &tail[end - 32..];
+        let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as usize;
+        let has_header = footer[23] & 0x80 != 0;
+        let size = size + if has_header { 32 } else { 0 };
+
+        if size <= end {
+            return Some(("APEv2", end - size));
+        }
+    }
+
+    if tail.len() >= 15 && &tail[end - 9..] == b"LYRICS200" {
+        let size = ::std::str::from_utf8(&tail[end - 15..end - 9])
+            .ok()
+            .and_then(|size| size.parse::<usize>().ok());
+
+        if let Some(size) = size.filter(|size| size + 15 <= end) {
+            return Some(("Lyrics3", end - 15 - size));
+        }
+    }
+
+    if tail.len() >= 9 && &tail[end - 9..] == b"LYRICSEND" {
+        //
+        // Lyrics3 version 1 is at most 5100 bytes with "LYRICSBEGIN".
+        //
+        let from = end.saturating_sub(5100 + 20);
+        let begin = tail[from..end - 9].windows(11).rposition(|w| w == b"LYRICSBEGIN");
+
+        if let Some(begin) = begin {
+            return Some(("Lyrics3", from + begin));
+        }
+    }
+
+    None
+}
+
+//
+// Tags of a file and their bytes.
+//
+#[derive(Debug, Default)]
+pub struct Layout {
+    //
+    // the major version and the flags byte of the first ID3v2 header
+    //
+    pub id3v2: Option<(u8, u8)>,
+    //
+    // bytes of ID3v2 tags with headers, footers and padding
+    //
+    pub id3v2_len: usize,
+    pub padding: usize,
+    //
+    // (name, start, length) of tags at the end, from the last one.
+    //
+    pub trailing: Vec<(&'static str, usize, usize)>,
+    pub audio_len: usize,
+}
+
+//
+// Bytes of frames in an ID3v2 tag. padding starts where a frame id is zero.
+//
+pub fn id3v2_frames_len(bytes: &[u8], version: u8, flags: u8) -> usize {
+    let syncsafe = |b: &[u8]| b.iter().fold(0usize, |size, b| (size << 7) | (*b as usize & 0x7f));
+    let plain = |b: &[u8]| b.iter().fold(0usize, |size, b| (size << 8) | *b as usize);
+
+    let mut at = 0;
+
+    //
+    // an extended header of version 3 has a size without itself, and of version 4 with itself.
+    //
+    if version > 2 && flags & 0x40 != 0 && bytes.len() >= 4 {
+        at = if version == 4 { syncsafe(&bytes[..4]) } else { plain(&bytes[..4]) + 4 };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+
+    while at + header_len <= bytes.len() && bytes[at] != 0 {
+        let size = match version {
+            2 => plain(&bytes[at + 3..at + 6]),
+            3 => plain(&bytes[at + 4..at + 8]),
+            _ => syncsafe(&bytes[at + 4..at + 8]),
+        };
+
+        if !bytes[at..at + id_len].iter().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()) {
+            break;
+        }
+        at = (at + header_len + size).min(bytes.len());
+    }
+
+    at
+}
+
+//
+// (id, frame, body) of frames in the first ID3v2 tag, as they are on disk.
+// a frame is bytes with its header. a frame that is compressed, encrypted, grouped or
+// unsynchronised is not read.
+//
+pub fn id3v2_frames(bytes: &[u8]) -> Vec<(String, &[u8], &[u8])> {
+    let mut frames = Vec::new();
+
+    if bytes.len() < 10 || &bytes[..3] != b"ID3" || bytes[3] < 2 || bytes[5] & 0x80 != 0 {
+        return frames;
+    }
+
+    let (version, flags) = (bytes[3], bytes[5]);
+
+    //
+    // the bit of an extended header is compression in version 2.
+    //
+    if version == 2 && flags & 0x40 != 0 {
+        return frames;
+    }
+
+    let size = bytes[6..10].iter().fold(0usize, |size, b| (size << 7) | (*b as usize & 0x7f));
+    let body = &bytes[10..(10 + size).min(bytes.len())];
+    let end = id3v2_frames_len(body, version, flags);
+
+    let mut at = 0;
+    if version > 2 && flags & 0x40 != 0 && body.len() >= 4 {
+        let plain = body[..4].iter().fold(0usize, |size, b| (size << 8) | *b as usize);
+        at = if version == 4 { syncsafe_len(&body[..4]) } else { plain + 4 };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+
+    while at + header_len <= end {
+        let header = &body[at..at + header_len];
+        let len = match version {
+            2 => header[3..6].iter().fold(0usize, |size, b| (size << 8) | *b as usize),
+            3 => header[4..8].iter().fold(0usize, |size, b| (size << 8) | *b as usize),
+            _ => syncsafe_len(&header[4..8]),
+        };
+        let format_flags = match version {
+            2 => 0,
+            3 => 0xe0,
+            _ => 0x4f,
+        };
+        let frame = &body[at..(at + header_len + len).min(end)];
+
+        if version == 2 || header[9] & format_flags == 0 {
+            frames.push((String::from_utf8_lossy(&header[..id_len]).to_string(),
+                         frame,
+                         &frame[header_len..]));
+        }
+        at += header_len + len;
+    }
+
+    frames
+}
+
+pub fn syncsafe_len(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0usize, |size, b| (size << 7) | (*b as usize & 0x7f))
+}
+
+pub fn layout(bytes: &[u8]) -> Layout {
+    let mut layout = Layout::default();
+    let (start, end) = payload(bytes);
+
+    if bytes.len() >= 10 && &bytes[..3] == b"ID3" {
+        let (version, flags) = (bytes[3], bytes[5]);
+        let size = bytes[6..10].iter().fold(0usize, |size, b| (size << 7) | (*b as usize & 0x7f));
+        let body = &bytes[10..(10 + size).min(bytes.len())];
+
+        let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+
+        layout.id3v2 = Some((version, flags));
+        layout.id3v2_len = start;
+        layout.padding = start.saturating_sub(10 + footer + id3v2_frames_len(body, version, flags));
+    }
+
+    let mut tag_end = bytes.len();
+    while let Some((name, tag_start)) = trailing_tag(bytes, tag_end) {
+        if tag_start < end {
+            break;
+        }
+        layout.trailing.push((name, tag_start, tag_end - tag_start));
+        tag_end = tag_start;
+    }
+
+    layout.audio_len = end - start;
+    layout
+}
+
+//
+// (start, end) of audio bytes without tags.
+//
+pub fn payload(bytes: &[u8]) -> (usize, usize) {
+    let start = id3v2_end(bytes, 0);
+    let mut end = bytes.len();
+
+    while let Some((_, tag_start)) = trailing_tag(bytes, end) {
+        if tag_start < start {
+            break;
+        }
+        end = tag_start;
+    }
+
+    (start, end.max(start))
+}
+
+//
+// A summary of an audio stream.
+//
+#[derive(Debug)]
+pub struct Info {
+    //
+    // seconds
+    //
+    pub duration: f64,
+    //
+    // an average kbps
+    //
+    pub bitrate: u32,
+}
+
+//
+// The first frame from `from` that is followed by a frame of the same stream, or the end.
+// a sync of 11 bits can be in audio data, so a header alone is not enough.
+//
+pub fn sync(audio: &[u8], from: usize) -> Option<usize> {
+    (from..audio.len()).find(|at| match Header::parse(&audio[*at..]) {
+        Some(header) => {
+            let next = at + header.frame_len();
+            next == audio.len() ||
+            next < audio.len() &&
+            Header::parse(&audio[next..]).is_some_and(|next| next.is_same_stream(&header))
+        }
+        None => false,
+    })
+}
+
+//
+// A frame of a Xing, Info or VBRI header that an encoder puts before audio frames.
+//
+pub fn is_info_frame(frame: &[u8]) -> bool {
+    frame.windows(4).take(64).any(|w| w == b"Xing" || w == b"Info" || w == b"VBRI")
+}
+
+//
+// Frames from the first sync. a Xing, Info or VBRI frame is not counted.
+//
+pub fn info(bytes: &[u8]) -> Option<Info> {
+    let (start, end) = payload(bytes);
+    let audio = &bytes[start..end];
+
+    let mut at = sync(audio, 0)?;
+
+    let first = Header::parse(&audio[at..])?;
+    let mut frames = 0;
+    let mut samples = 0u64;
+    let mut size = 0u64;
+
+    while let Some(header) = Header::parse(&audio[at..]) {
+        let len = header.frame_len();
+        if !header.is_same_stream(&first) || at + len > audio.len() {
+            break;
+        }
+
+        let frame = &audio[at..at + len];
+        let tagged = frames == 0 && is_info_frame(frame);
+
+        if !tagged {
+            frames += 1;
+            samples += header.samples() as u64;
+            size += len as u64;
+        }
+        at += len;
+    }
+
+    let duration = samples as f64 / first.sample_rate as f64;
+    let bitrate = if duration > 0.0 {
+        (size as f64 * 8.0 / duration / 1000.0).round() as u32
+    } else {
+        0
+    };
+
+    Some(Info {
+        duration,
+        bitrate,
+    })
+}
+
+pub fn read(file: &Path) -> Option<Vec<u8>> {
+    match fs::read(file) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            warn!("Can not read: {:?}, {:?}", file, e);
+            None
+        }
+    }
+}
+
+//
+// 205.3 => "3:25"
+//
+pub fn minutes(duration: f64) -> String {
+    let seconds = duration.round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // A version 3 tag of TIT2 "Title" and zero padding.
+    //
+    fn id3v2(padding: usize) -> Vec<u8> {
+        let frame = b"TIT2\x00\x00\x00\x06\x00\x00\x00Title";
+        let size = frame.len() + padding;
+
+        let mut bytes = b"ID3\x03\x00\x00".to_vec();
+        bytes.extend((0..4).rev().map(|i| (size >> (7 * i)) as u8 & 0x7f));
+        bytes.extend_from_slice(frame);
+        bytes.extend(vec![0; padding]);
+        bytes
+    }
+
+    fn id3v1() -> Vec<u8> {
+        let mut bytes = b"TAG".to_vec();
+        bytes.resize(128, b' ');
+        bytes
+    }
+
+    fn apev2(items: usize) -> Vec<u8> {
+        let mut bytes = vec![b'i'; items];
+        bytes.extend_from_slice(b"APETAGEX");
+        bytes.extend_from_slice(&2000u32.to_le_bytes());
+        bytes.extend_from_slice(&(items as u32 + 32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        bytes
+    }
+
+    fn lyrics3() -> Vec<u8> {
+        let mut bytes = b"LYRICSBEGININD00002".to_vec();
+        bytes.extend_from_slice(b"10");
+        bytes.extend_from_slice(format!("{:06}", bytes.len()).as_bytes());
+        bytes.extend_from_slice(b"LYRICS200");
+        bytes
+    }
+
+    #[test]
+    fn id3v2_end_of_tags() {
+        let mut bytes = id3v2(20);
+        let first = bytes.len();
+        bytes.extend(id3v2(0));
+        let second = bytes.len();
+        bytes.extend_from_slice(&[0, 0, 0xff, 0xfb]);
+
+        assert_eq!(id3v2_end(&bytes, 0), second + 2);
+        assert_eq!(id3v2_end(&bytes, first), second + 2);
+        assert_eq!(id3v2_end(&[0xff, 0xfb], 0), 0);
+        assert_eq!(id3v2_end(b"ID3\x03\x00\x00\x00\x00\x7f\x7f", 0), 10);
+    }
+
+    #[test]
+    fn trailing_tags() {
+        let audio = vec![0xff; 400];
+
+        let mut bytes = audio.clone();
+        bytes.extend(id3v1());
+        assert_eq!(trailing_tag(&bytes, bytes.len()), Some(("ID3v1", 400)));
+
+        let mut bytes = audio.clone();
+        bytes.extend(apev2(50));
+        assert_eq!(trailing_tag(&bytes, bytes.len()), Some(("APEv2", 400)));
+
+        let mut bytes = audio.clone();
+        bytes.extend(lyrics3());
+        assert_eq!(trailing_tag(&bytes, bytes.len()), Some(("Lyrics3", 400)));
+
+        let mut bytes = audio.clone();
+        bytes.extend_from_slice(b"LYRICSBEGINla la LYRICSEND");
+        assert_eq!(trailing_tag(&bytes, bytes.len()), Some(("Lyrics3", 400)));
+
+        assert_eq!(trailing_tag(&audio, audio.len()), None);
+    }
+
+    #[test]
+    fn layout_of_tags() {
+        let mut bytes = id3v2(20);
+        bytes.extend_from_slice(&[0xff; 400]);
+        bytes.extend(apev2(50));
+        bytes.extend(id3v1());
+
+        let tagged = layout(&bytes);
+        let id3v2_len = id3v2(20).len();
+
+        assert_eq!(tagged.id3v2, Some((3, 0)));
+        assert_eq!(tagged.id3v2_len, id3v2_len);
+        assert_eq!(tagged.padding, 20);
+        assert_eq!(tagged.trailing,
+                   vec![("ID3v1", id3v2_len + 482, 128), ("APEv2", id3v2_len + 400, 82)]);
+        assert_eq!(tagged.audio_len, 400);
+
+        let plain = layout(&[0xff; 400]);
+
+        assert_eq!(plain.id3v2, None);
+        assert_eq!(plain.padding, 0);
+        assert!(plain.trailing.is_empty());
+        assert_eq!(plain.audio_len, 400);
+    }
+}
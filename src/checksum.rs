@@ -0,0 +1,238 @@
+use artwork;
+use audio::{self, Header};
+use clap;
+use md5;
+use tag;
+
+use rtag::frame::FrameBody;
+
+use std::path::{Path, PathBuf};
+use std::process;
+
+use {All, ViewFrame, ViewHead, load, update};
+
+//
+// A description of TXXX that has a checksum.
+//
+const DESCRIPTION: &str = "AUDIO_MD5";
+
+//
+// md5 of MPEG audio frames. tags, bytes between frames and a Xing, Info or VBRI frame are not in
+// it, so it is the same after retagging or remuxing.
+//
+pub fn of(bytes: &[u8]) -> String {
+    let (start, end) = audio::payload(bytes);
+    let audio = &bytes[start..end];
+
+    let mut context = md5::Context::new();
+    let mut at = audio::sync(audio, 0);
+    let mut first = true;
+
+    while let Some(from) = at {
+        let len = Header::parse(&audio[from..]).map_or(1, |header| header.frame_len());
+        let frame = &audio[from..(from + len).min(audio.len())];
+
+        if !(first && audio::is_info_frame(frame)) {
+            context.consume(frame);
+        }
+
+        first = false;
+        at = audio::sync(audio, from + len);
+    }
+
+    format!("{:x}", context.compute())
+}
+
+pub fn compute(file: &Path) -> Option<String> {
+    audio::read(file).map(|bytes| of(&bytes))
+}
+
+fn stored(all: &All) -> Option<String> {
+    all.frames.as_ref()?.iter().find_map(|vf| match vf.body {
+        FrameBody::TXXX(ref frame) if frame.description == DESCRIPTION => Some(frame.value.clone()),
+        _ => None,
+    })
+}
+
+fn paths(matches: &clap::ArgMatches) -> Vec<PathBuf> {
+    matches.values_of("INPUT")
+        .unwrap()
+        .filter_map(|file| match PathBuf::from(file).canonicalize() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn store(matches: clap::ArgMatches) {
+    let dry_run = matches.is_present("dry-run");
+
+    for path in paths(&matches) {
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let md5 = match compute(&path) {
+            Some(md5) => md5,
+            None => continue,
+        };
+
+        let old = stored(&all);
+        if old.as_ref() == Some(&md5) {
+            debug!("checksum is already stored: {:?}", path);
+            continue;
+        }
+
+        let version = match all.head {
+            Some(ref head) => head.version.parse().unwrap_or(4),
+            None => 4,
+        };
+
+        let id = format!("TXXX:{}", DESCRIPTION);
+        let fbody = match tag::text_frame(&id, &md5, version) {
+            Some(fbody) => fbody,
+            None => continue,
+        };
+
+        match old {
+            Some(old) => println!("{}\n\t{}: {} -> {}", path.display(), id, old, md5),
+            None => println!("{}\n\t{}: {}", path.display(), id, md5),
+        }
+
+        if dry_run {
+            continue;
+        }
+
+        //
+        // a file without version 2 tag gets version 4.
+        //
+        if all.head.is_none() {
+            all.head = Some(ViewHead {
+                version: "4".to_string(),
+                flags: None,
+            });
+        }
+
+        let frames = all.frames.get_or_insert_with(Vec::new);
+        frames.retain(|vf| match vf.body {
+            FrameBody::TXXX(ref frame) => frame.description != DESCRIPTION,
+            _ => true,
+        });
+        frames.push(ViewFrame {
+            flags: None,
+            body: fbody,
+            picture: None,
+            resource: None,
+            genre: None,
+        });
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+//
+// exit with 1 if a file does not match or can not be read.
+//
+pub fn verify(matches: clap::ArgMatches) {
+    let files = matches.values_of("INPUT").unwrap().count();
+    let paths = paths(&matches);
+    let mut failed = paths.len() < files;
+
+    for path in paths {
+        let all = match load(&path) {
+            Some(all) => all,
+            None => {
+                error!("Can not read a tag: {:?}", path);
+                failed = true;
+                continue;
+            }
+        };
+
+        let stored = match stored(&all) {
+            Some(stored) => stored,
+            None => {
+                println!("{}\n\tno TXXX:{}", path.display(), DESCRIPTION);
+                continue;
+            }
+        };
+
+        match compute(&path) {
+            Some(ref md5) if *md5 == stored.trim() => debug!("ok: {:?}", path),
+            Some(md5) => {
+                println!("{}\n\tmismatch: stored {}, audio {}", path.display(), stored, md5);
+                failed = true;
+            }
+            None => {
+                error!("Can not read: {:?}", path);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // Frames of MPEG 1 layer III, 128 kbps and 44100 Hz. a frame is 417 bytes.
+    //
+    fn frames(count: usize, seed: u8) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for i in 0..count {
+            bytes.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+            bytes.extend((0..413).map(|j| (i * 7 + j) as u8 ^ seed).map(|b| b & 0x7f));
+        }
+        bytes
+    }
+
+    fn id3v2(text: &str) -> Vec<u8> {
+        let mut frame = b"TIT2".to_vec();
+        frame.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0]);
+        frame.extend_from_slice(text.as_bytes());
+
+        let mut bytes = b"ID3\x03\x00\x00\x00\x00\x00".to_vec();
+        bytes.push(frame.len() as u8 + 10);
+        bytes.extend(frame);
+        bytes.extend_from_slice(&[0; 10]);
+        bytes
+    }
+
+    #[test]
+    fn checksum_of_audio_frames() {
+        let audio = frames(5, 0);
+        let md5 = of(&audio);
+
+        let mut tagged = id3v2("Title");
+        tagged.extend_from_slice(&audio);
+        assert_eq!(of(&tagged), md5);
+
+        let mut retagged = id3v2("Another title");
+        retagged.extend_from_slice(&audio);
+        retagged.extend_from_slice(b"TAG");
+        retagged.resize(retagged.len() + 125, b' ');
+        assert_eq!(of(&retagged), md5);
+
+        let mut junk = b"junk".to_vec();
+        junk.extend_from_slice(&audio);
+        assert_eq!(of(&junk), md5);
+
+        let mut xing = frames(1, 0x55);
+        xing[40..44].copy_from_slice(b"Xing");
+        xing.extend_from_slice(&audio);
+        assert_eq!(of(&xing), md5);
+
+        assert_ne!(of(&frames(5, 1)), md5);
+    }
+}
@@ -0,0 +1,441 @@
+use artwork;
+use clap;
+use serde_json;
+use tag;
+
+use regex::Regex;
+use rtag::frame::{FrameBody, framebody_to_id, id};
+use rtag::frame::types::TextEncoding;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use {All, load, update};
+
+const FRAMES: [&str; 4] = ["TIT2", "TPE1", "TPE2", "TALB"];
+
+//
+// whitespace but NUL that separates values.
+//
+const COLLAPSE: &str = r"[^\S\x00]+";
+//
+// "feat" after a space or a bracket, not the start of a title. ex) "Ft Lauderdale"
+//
+const FEAT: &str = r"(?i)(\s[(\[]?|[(\[])(?:feat\.?|ft\.?|featuring)(?:\s+|$)";
+
+//
+// Words that are not capitalized by `title-case` unless they are the first or the last.
+// "feat." is never capitalized.
+//
+const SMALL_WORDS_EN: [&str; 25] = ["a", "an", "and", "as", "at", "but", "by", "for", "from", "in",
+                                    "into", "nor", "of", "on", "or", "over", "per", "so", "the",
+                                    "to", "up", "via", "vs", "with", "yet"];
+const SMALL_WORDS_FR: [&str; 19] = ["à", "au", "aux", "avec", "dans", "de", "des", "du", "en",
+                                    "et", "l", "la", "le", "les", "ou", "par", "pour", "sur", "un"];
+const SMALL_WORDS_DE: [&str; 19] = ["am", "an", "auf", "aus", "das", "dem", "den", "der", "des",
+                                    "die", "ein", "eine", "für", "im", "in", "mit", "oder", "und",
+                                    "von"];
+const SMALL_WORDS_ES: [&str; 17] = ["a", "con", "de", "del", "el", "en", "la", "las", "lo", "los",
+                                    "o", "para", "por", "un", "una", "y", "sin"];
+
+//
+// A rule of a json rule file. ex)
+// [
+//   "trim",
+//   {"rule": "title-case", "language": "en", "exceptions": ["AC/DC"]},
+//   {"rule": "replace", "pattern": "\\s*\\(Remastered\\)$", "with": ""}
+// ]
+//
+#[derive(Debug, Deserialize)]
+struct Rule {
+    rule: String,
+    language: Option<String>,
+    exceptions: Option<Vec<String>>,
+    pattern: Option<String>,
+    with: Option<String>,
+    //
+    // a regex of `collapse`, `feat` and `replace`. it is compiled by `rules`.
+    //
+    #[serde(skip_deserializing)]
+    regex: Option<Regex>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RuleItem {
+    Name(String),
+    Rule(Rule),
+}
+
+impl Rule {
+    fn named(name: &str) -> Rule {
+        Rule {
+            rule: name.trim().to_string(),
+            language: None,
+            exceptions: None,
+            pattern: None,
+            with: None,
+            regex: None,
+        }
+    }
+}
+
+//
+// A comma list of rule names, or a json file of rules.
+//
+fn rules(value: &str) -> Option<Vec<Rule>> {
+    let mut rules: Vec<Rule> = if Path::new(value).is_file() {
+        let fs = match File::open(value) {
+            Ok(fs) => fs,
+            Err(e) => {
+                error!("Can not open a rule file: {}, {:?}", value, e);
+                return None;
+            }
+        };
+
+        let items: Vec<RuleItem> = match serde_json::from_reader(fs) {
+            Ok(items) => items,
+            Err(e) => {
+                error!("Invalid rule file: {}, {:?}", value, e);
+                return None;
+            }
+        };
+
+        items.into_iter()
+            .map(|item| match item {
+                RuleItem::Name(name) => Rule::named(&name),
+                RuleItem::Rule(rule) => rule,
+            })
+            .collect()
+    } else {
+        value.split(',').filter(|name| !name.trim().is_empty()).map(Rule::named).collect()
+    };
+
+    let collapse = Regex::new(COLLAPSE).unwrap();
+    let feat = Regex::new(FEAT).unwrap();
+
+    for rule in &mut rules {
+        match rule.rule.as_str() {
+            "trim" | "title-case" => (),
+            "collapse" => rule.regex = Some(collapse.clone()),
+            "feat" => rule.regex = Some(feat.clone()),
+            "replace" => {
+                match rule.pattern {
+                    Some(ref pattern) => {
+                        match Regex::new(pattern) {
+                            Ok(regex) => rule.regex = Some(regex),
+                            Err(e) => {
+                                error!("Invalid pattern: {}, {:?}", pattern, e);
+                                return None;
+                            }
+                        }
+                    }
+                    None => {
+                        error!("No pattern of a replace rule");
+                        return None;
+                    }
+                }
+            }
+            _ => {
+                error!("Unknown rule: {}. (trim|collapse|title-case|feat|replace)", rule.rule);
+                return None;
+            }
+        }
+    }
+
+    Some(rules)
+}
+
+//
+// A language of ISO 639. "eng", "en" => "en"
+//
+fn small_words(language: &str) -> &'static [&'static str] {
+    match language.to_lowercase().as_str() {
+        "fr" | "fra" | "fre" => &SMALL_WORDS_FR,
+        "de" | "deu" | "ger" => &SMALL_WORDS_DE,
+        "es" | "spa" => &SMALL_WORDS_ES,
+        _ => &SMALL_WORDS_EN,
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(|ch| ch.to_lowercase())).collect(),
+        None => String::new(),
+    }
+}
+
+//
+// "THE WALL OF SOUND" => "The Wall of Sound". a word in mixed case is kept. ex) "McCartney"
+//
+fn title_case(value: &str, small: &[&str], exceptions: &[String]) -> String {
+    let words: Vec<&str> = value.split(' ').collect();
+    let last = words.len().saturating_sub(1);
+
+    words.iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let bare: String = word.chars().filter(|ch| ch.is_alphanumeric()).collect();
+            let lower = bare.to_lowercase();
+
+            if let Some(exception) = exceptions.iter().find(|e| e.to_lowercase() == word.to_lowercase()) {
+                return exception.clone();
+            }
+
+            let upper = bare.chars().all(|ch| !ch.is_lowercase());
+            let lowered = bare.chars().all(|ch| !ch.is_uppercase());
+
+            if !upper && !lowered {
+                word.to_string()
+            } else if lower == "feat" || i != 0 && i != last && small.contains(&lower.as_str()) {
+                word.to_lowercase()
+            } else {
+                capitalize(word)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn apply(rule: &Rule, value: &str, language: &str) -> String {
+    match rule.rule.as_str() {
+        //
+        // NUL is also trimmed. some taggers pad a value with it.
+        //
+        "trim" => value.trim_matches(|ch: char| ch.is_whitespace() || ch == '\0').to_string(),
+        "collapse" => rule.regex.as_ref().unwrap().replace_all(value, " ").to_string(),
+        "title-case" => {
+            let language = rule.language.as_deref().unwrap_or(language);
+            let exceptions = rule.exceptions.clone().unwrap_or_default();
+
+            //
+            // values of version 4 are separated by null.
+            //
+            value.split('\0')
+                .map(|value| title_case(value, small_words(language), &exceptions))
+                .collect::<Vec<_>>()
+                .join("\0")
+        }
+        "feat" => {
+            rule.regex.as_ref().unwrap().replace_all(value, "${1}feat. ").trim_end().to_string()
+        }
+        "replace" => {
+            let with = rule.with.as_deref().unwrap_or("");
+            rule.regex.as_ref().unwrap().replace_all(value, with).to_string()
+        }
+        _ => value.to_string(),
+    }
+}
+
+//
+// A frame of a new value. the encoding is kept if it can have the value, and is the one of
+// `tag::text_frame` if not. a language and a description of COMM are kept.
+//
+fn replace(fbody: &mut FrameBody, key: &str, value: &str, version: u8) -> bool {
+    let mut new = match tag::text_frame(key, value, version) {
+        Some(new) => new,
+        None => return false,
+    };
+
+    if let (FrameBody::COMM(ref old), FrameBody::COMM(ref mut new)) = (&*fbody, &mut new) {
+        new.language = old.language.clone();
+        new.short_description = old.short_description.clone();
+    }
+
+    if let Some(old) = tag::encoding(fbody) {
+        if *old != TextEncoding::ISO88591 || value.chars().all(|ch| (ch as u32) < 0x100) {
+            if let Some(encoding) = tag::encoding_mut(&mut new) {
+                *encoding = old.clone();
+            }
+        }
+    }
+
+    *fbody = new;
+    true
+}
+
+//
+// (changed, diff)
+//
+fn clean_all(all: &mut All, rules: &[Rule], frames: &[String]) -> (bool, Vec<String>) {
+    let mut changed = false;
+    let mut diff = Vec::new();
+
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => return (changed, diff),
+    };
+
+    let vfs = match all.frames {
+        Some(ref mut vfs) => vfs,
+        None => return (changed, diff),
+    };
+
+    //
+    // a language for `title-case` without one. TLAN is a code of ISO 639-2.
+    //
+    let language = vfs.iter()
+        .find(|vf| framebody_to_id(&vf.body, 4) == id::TLAN)
+        .and_then(|vf| tag::text_of(&vf.body))
+        .map(|(_, language)| language.trim().to_string())
+        .unwrap_or_else(|| "en".to_string());
+
+    for vf in vfs.iter_mut() {
+        let (key, value) = match tag::text_of(&vf.body) {
+            Some(text) => text,
+            None => continue,
+        };
+
+        let id = framebody_to_id(&vf.body, 4);
+        if !frames.iter().any(|frame| frame == id || *frame == key) {
+            continue;
+        }
+
+        let cleaned = rules.iter().fold(value.clone(), |value, rule| apply(rule, &value, &language));
+        if cleaned == value {
+            continue;
+        }
+
+        if replace(&mut vf.body, &key, &cleaned, version) {
+            diff.push(format!("- {}: {:?}", key, value));
+            diff.push(format!("+ {}: {:?}", key, cleaned));
+            changed = true;
+        }
+    }
+
+    (changed, diff)
+}
+
+pub fn clean(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    let rules = match rules(matches.value_of("clean").unwrap()) {
+        Some(rules) => rules,
+        None => return,
+    };
+
+    let frames: Vec<String> = match matches.value_of("clean-frames") {
+        Some(ids) => {
+            ids.split(',').map(|id| id.trim().to_string()).filter(|id| !id.is_empty()).collect()
+        }
+        None => FRAMES.iter().map(|id| id.to_string()).collect(),
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let (changed, diff) = clean_all(&mut all, &rules, &frames);
+
+        if !changed {
+            debug!("already clean: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for line in diff {
+            println!("\t{}", line);
+        }
+
+        if dry_run {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(names: &str, value: &str) -> String {
+        rules(names).unwrap().iter().fold(value.to_string(), |value, rule| apply(rule, &value, "en"))
+    }
+
+    #[test]
+    fn trim_and_collapse() {
+        assert_eq!(clean("trim", " \0Title \0"), "Title");
+        assert_eq!(clean("collapse", "A  \t B\0C   D"), "A B\0C D");
+        assert_eq!(clean("trim,collapse", "  A   B  "), "A B");
+    }
+
+    #[test]
+    fn title_case_of_words() {
+        assert_eq!(clean("title-case", "THE WALL OF SOUND"), "The Wall of Sound");
+        assert_eq!(clean("title-case", "of mice and men"), "Of Mice and Men");
+        assert_eq!(clean("title-case", "what are you looking at"), "What Are You Looking At");
+        assert_eq!(clean("title-case", "paul mcCartney FEAT. wings"), "Paul mcCartney feat. Wings");
+        assert_eq!(clean("title-case", "one\0TWO OF THREE"), "One\0Two of Three");
+
+        let rule = Rule {
+            language: Some("fr".to_string()),
+            exceptions: Some(vec!["AC/DC".to_string()]),
+            ..Rule::named("title-case")
+        };
+        assert_eq!(apply(&rule, "LE CHANT DES AC/DC", "en"), "Le Chant des AC/DC");
+    }
+
+    #[test]
+    fn feat() {
+        assert_eq!(clean("feat", "Song ft. Artist"), "Song feat. Artist");
+        assert_eq!(clean("feat", "Song (Featuring Artist)"), "Song (feat. Artist)");
+        assert_eq!(clean("feat", "Song [FT Artist]"), "Song [feat. Artist]");
+        assert_eq!(clean("feat", "Song(feat Artist)"), "Song(feat. Artist)");
+        assert_eq!(clean("feat", "Song feat."), "Song feat.");
+
+        assert_eq!(clean("feat", "Ft Lauderdale"), "Ft Lauderdale");
+        assert_eq!(clean("feat", "Featuring Artist"), "Featuring Artist");
+        assert_eq!(clean("feat", "Left Craft"), "Left Craft");
+    }
+
+    #[test]
+    fn replace_rule() {
+        let mut rule = Rule {
+            pattern: Some(r"\s*\(Remastered\)$".to_string()),
+            ..Rule::named("replace")
+        };
+        rule.regex = Some(Regex::new(rule.pattern.as_ref().unwrap()).unwrap());
+        assert_eq!(apply(&rule, "Song (Remastered)", "en"), "Song");
+
+        rule.with = Some(" [R]".to_string());
+        assert_eq!(apply(&rule, "Song (Remastered)", "en"), "Song [R]");
+    }
+
+    #[test]
+    fn rules_of_names() {
+        assert_eq!(rules("trim, collapse,,feat").unwrap().len(), 3);
+        assert!(rules("trim,unknown").is_none());
+        assert!(rules("replace").is_none());
+    }
+
+    #[test]
+    fn small_words_of_language() {
+        assert_eq!(small_words("eng"), &SMALL_WORDS_EN[..]);
+        assert_eq!(small_words("FRE"), &SMALL_WORDS_FR[..]);
+        assert_eq!(small_words("deu"), &SMALL_WORDS_DE[..]);
+        assert_eq!(small_words("es"), &SMALL_WORDS_ES[..]);
+        assert_eq!(small_words("kor"), &SMALL_WORDS_EN[..]);
+
+        for words in [&SMALL_WORDS_EN[..], &SMALL_WORDS_FR, &SMALL_WORDS_DE, &SMALL_WORDS_ES].iter() {
+            for (i, word) in words.iter().enumerate() {
+                assert_eq!(word.to_lowercase(), *word);
+                assert!(!words[i + 1..].contains(word), "duplicated: {}", word);
+            }
+        }
+    }
+}
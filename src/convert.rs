@@ -0,0 +1,431 @@
+use artwork;
+use clap;
+use tag;
+
+use rtag::frame::{FrameBody, IPLS, TEXT, framebody_to_id};
+use rtag::frame::types::{HeadFlag, TextEncoding};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use {All, ViewFrame, load, update};
+
+//
+// Frames that are only in version 3, or only in version 4. version 2 has much less frames.
+// ids are names of rtag `FrameBody`.
+//
+const V3_ONLY: [&str; 9] = ["EQUA", "IPLS", "RVAD", "TDAT", "TIME", "TORY", "TRDA", "TSIZ", "TYER"];
+const V4_ONLY: [&str; 18] = ["ASPI", "EQU2", "RVA2", "SEEK", "SIGN", "TDEN", "TDOR", "TDRC",
+                             "TDRL", "TDTG", "TIPL", "TMCL", "TMOO", "TPRO", "TSOA", "TSOP",
+                             "TSOT", "TSST"];
+
+fn describe(fbody: &FrameBody) -> String {
+    match tag::text_of(fbody) {
+        Some((id, text)) => format!("{} \"{}\"", id, text),
+        None => framebody_to_id(fbody, 4).to_string(),
+    }
+}
+
+//
+// "2001-05-02T13:20:10" => ["2001", "05", "02", "13", "20", "10"]
+//
+fn timestamp(tdrc: &str) -> Vec<&str> {
+    tdrc.trim().split(['-', 'T', ' ', ':']).collect()
+}
+
+//
+// Date frames of version 4. TYER, TDAT(DDMM), TIME(HHMM) => TDRC(yyyy-MM-ddTHH:mm)
+//
+fn to_tdrc(dates: &HashMap<&str, String>, lost: &mut Vec<String>) -> Vec<FrameBody> {
+    let mut tdrc = match dates.get("TYER").or_else(|| dates.get("TDRC")) {
+        Some(year) => timestamp(year)[0].to_string(),
+        None => {
+            for (id, value) in dates {
+                lost.push(format!("{} \"{}\": no year", id, value));
+            }
+            return Vec::new();
+        }
+    };
+
+    let digits = |value: &String| value.len() == 4 && value.chars().all(|ch| ch.is_ascii_digit());
+
+    match dates.get("TDAT") {
+        Some(tdat) if digits(tdat) => {
+            tdrc.push_str(&format!("-{}-{}", &tdat[2..], &tdat[..2]));
+
+            match dates.get("TIME") {
+                Some(time) if digits(time) => {
+                    tdrc.push_str(&format!("T{}:{}", &time[..2], &time[2..]))
+                }
+                Some(time) => lost.push(format!("TIME \"{}\"", time)),
+                None => (),
+            }
+        }
+        Some(tdat) => lost.push(format!("TDAT \"{}\"", tdat)),
+        None => {
+            if let Some(time) = dates.get("TIME") {
+                lost.push(format!("TIME \"{}\": no TDAT", time));
+            }
+        }
+    }
+
+    tag::text_frame("TDRC", &tdrc, 4).into_iter().collect()
+}
+
+//
+// Date frames of version 2 and 3. TDRC(yyyy-MM-ddTHH:mm:ss) => TYER, TDAT(DDMM), TIME(HHMM)
+//
+fn from_tdrc(dates: &HashMap<&str, String>, to: u8, lost: &mut Vec<String>) -> Vec<FrameBody> {
+    let tdrc = match dates.get("TDRC") {
+        Some(tdrc) => tdrc,
+        None => {
+            //
+            // version 2 to 3, 3 to 2 have the same date frames.
+            //
+            return ["TYER", "TDAT", "TIME"]
+                .iter()
+                .filter_map(|id| dates.get(id).and_then(|value| tag::text_frame(id, value, to)))
+                .collect();
+        }
+    };
+
+    let parts = timestamp(tdrc);
+    let mut ret = Vec::new();
+
+    ret.extend(tag::text_frame("TYER", parts[0], to));
+
+    if parts.len() > 2 {
+        ret.extend(tag::text_frame("TDAT", &format!("{}{}", parts[2], parts[1]), to));
+    }
+
+    if parts.len() > 4 {
+        ret.extend(tag::text_frame("TIME", &format!("{}{}", parts[3], parts[4]), to));
+    }
+
+    if parts.len() == 2 || parts.len() == 4 || parts.len() > 5 {
+        lost.push(format!("TDRC \"{}\": partly", tdrc));
+    }
+
+    ret
+}
+
+//
+// (converted frames, what could not be represented)
+//
+pub fn convert_frames(frames: Vec<ViewFrame>, to: u8) -> (Vec<ViewFrame>, Vec<String>) {
+    let mut ret = Vec::new();
+    let mut lost = Vec::new();
+    let mut dates = HashMap::new();
+    let mut dates_at = None;
+
+    for vf in frames {
+        let id = framebody_to_id(&vf.body, 4);
+
+        if let "TDRC" | "TYER" | "TDAT" | "TIME" = id {
+            if let Some((_, text)) = tag::text_of(&vf.body) {
+                dates.insert(id, text);
+                dates_at.get_or_insert(ret.len());
+            }
+            continue;
+        }
+
+        let fbody = match (id, vf.body) {
+            ("TDOR", ref body) if to < 4 => {
+                match tag::text_of(body) {
+                    Some((_, text)) => {
+                        let year: String = text.trim().chars().take(4).collect();
+                        if year != text.trim() {
+                            lost.push(format!("TDOR \"{}\": partly", text));
+                        }
+                        tag::text_frame("TORY", &year, to)
+                    }
+                    None => None,
+                }
+            }
+            ("TORY", ref body) if to == 4 => {
+                tag::text_of(body).and_then(|(_, text)| tag::text_frame("TDOR", &text, to))
+            }
+            ("TIPL", FrameBody::TIPL(body)) if to < 4 => {
+                Some(FrameBody::IPLS(IPLS {
+                    text_encoding: body.text_encoding,
+                    people_list_strings: body.text,
+                }))
+            }
+            ("IPLS", FrameBody::IPLS(body)) if to == 4 => {
+                Some(FrameBody::TIPL(TEXT {
+                    text_encoding: body.text_encoding,
+                    text: body.people_list_strings,
+                }))
+            }
+            ("APIC", body) if to == 2 => Some(artwork::apic_to_pic(body)),
+            (_, body) => Some(artwork::pic_to_apic(body)),
+        };
+
+        let mut fbody = match fbody {
+            Some(fbody) => fbody,
+            None => continue,
+        };
+
+        let id = framebody_to_id(&fbody, 4);
+        let representable = match to {
+            2 => framebody_to_id(&fbody, 2).len() == 3,
+            3 => !V4_ONLY.contains(&id),
+            _ => !V3_ONLY.contains(&id),
+        };
+
+        if !representable {
+            lost.push(format!("{}: no frame in version {}", describe(&fbody), to));
+            continue;
+        }
+
+        //
+        // UTF-8 and UTF-16BE are only for version 4.
+        //
+        if to < 4 {
+            let legacy = tag::legacy_encoding(&fbody);
+            let encodes = tag::encodes(&fbody);
+
+            if let Some(encoding) = tag::encoding_mut(&mut fbody) {
+                if !encodes || matches!(*encoding, TextEncoding::UTF8 | TextEncoding::UTF16BE) {
+                    *encoding = legacy;
+                }
+            }
+
+            if !encodes && !tag::is_ascii(&fbody) {
+                lost.push(format!("{}: non-ASCII text in version {}", describe(&fbody), to));
+            }
+        }
+
+        ret.push(ViewFrame {
+            flags: if to == 2 { None } else { vf.flags },
+            body: fbody,
+            picture: None,
+            resource: None,
+            genre: None,
+        });
+    }
+
+    if let Some(at) = dates_at {
+        let date_frames = if to == 4 {
+            to_tdrc(&dates, &mut lost)
+        } else {
+            from_tdrc(&dates, to, &mut lost)
+        };
+
+        for (i, fbody) in date_frames.into_iter().enumerate() {
+            ret.insert(at + i,
+                       ViewFrame {
+                           flags: None,
+                           body: fbody,
+                           picture: None,
+                           resource: None,
+                           genre: None,
+                       });
+        }
+    }
+
+    (ret, lost)
+}
+
+fn convert_all(all: &mut All, to: u8) -> Vec<String> {
+    let (frames, lost) = convert_frames(all.frames.take().unwrap_or_default(), to);
+
+    all.frames = if frames.is_empty() { None } else { Some(frames) };
+
+    if let Some(ref mut head) = all.head {
+        head.version = to.to_string();
+
+        //
+        // compression of a header is only for version 2.
+        //
+        if let Some(flags) = head.flags.take() {
+            let flags: Vec<HeadFlag> = flags.into_iter()
+                .filter(|flag| to == 2 || *flag != HeadFlag::Compression)
+                .collect();
+            head.flags = if flags.is_empty() { None } else { Some(flags) };
+        }
+    }
+
+    lost
+}
+
+pub fn convert(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    let to: u8 = match matches.value_of("convert").unwrap().parse() {
+        Ok(to @ 2..=4) => to,
+        _ => {
+            error!("Unknown version: {}. (2|3|4)", matches.value_of("convert").unwrap());
+            return;
+        }
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let from = match all.head {
+            Some(ref head) => head.version.clone(),
+            None => {
+                debug!("no version 2 tag: {:?}", path);
+                continue;
+            }
+        };
+
+        if from == to.to_string() {
+            debug!("already version {}: {:?}", to, path);
+            continue;
+        }
+
+        let lost = convert_all(&mut all, to);
+
+        println!("{}: {} -> {}", path.display(), from, to);
+        for lost in lost {
+            println!("\tlost {}", lost);
+        }
+
+        if dry_run {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dates(pairs: &[(&'static str, &str)]) -> HashMap<&'static str, String> {
+        pairs.iter().map(|&(id, value)| (id, value.to_string())).collect()
+    }
+
+    fn texts(fbodies: &[FrameBody]) -> Vec<String> {
+        fbodies.iter().map(describe).collect()
+    }
+
+    fn frames(fbodies: Vec<FrameBody>) -> Vec<ViewFrame> {
+        fbodies.into_iter()
+            .map(|body| {
+                ViewFrame {
+                    flags: None,
+                    body,
+                    picture: None,
+                    resource: None,
+                    genre: None,
+                }
+            })
+            .collect()
+    }
+
+    fn bodies(frames: &[ViewFrame]) -> Vec<FrameBody> {
+        frames.iter().map(|vf| vf.body.clone()).collect()
+    }
+
+    fn text(id: &str, value: &str, version: u8) -> FrameBody {
+        tag::text_frame(id, value, version).unwrap()
+    }
+
+    #[test]
+    fn dates_to_tdrc() {
+        let mut lost = Vec::new();
+
+        let tdrc = to_tdrc(&dates(&[("TYER", "2001"), ("TDAT", "0205"), ("TIME", "1320")]), &mut lost);
+        assert_eq!(texts(&tdrc), vec!["TDRC \"2001-05-02T13:20\""]);
+
+        let tdrc = to_tdrc(&dates(&[("TYER", "2001"), ("TDAT", "2 May")]), &mut lost);
+        assert_eq!(texts(&tdrc), vec!["TDRC \"2001\""]);
+
+        let tdrc = to_tdrc(&dates(&[("TYER", "2001"), ("TIME", "1320")]), &mut lost);
+        assert_eq!(texts(&tdrc), vec!["TDRC \"2001\""]);
+
+        let tdrc = to_tdrc(&dates(&[("TDAT", "0205")]), &mut lost);
+        assert!(tdrc.is_empty());
+
+        assert_eq!(lost,
+                   vec!["TDAT \"2 May\"", "TIME \"1320\": no TDAT", "TDAT \"0205\": no year"]);
+    }
+
+    #[test]
+    fn dates_from_tdrc() {
+        let mut lost = Vec::new();
+
+        let frames = from_tdrc(&dates(&[("TDRC", "2001-05-02T13:20")]), 3, &mut lost);
+        assert_eq!(texts(&frames),
+                   vec!["TYER \"2001\"", "TDAT \"0205\"", "TIME \"1320\""]);
+        assert!(lost.is_empty());
+
+        let frames = from_tdrc(&dates(&[("TDRC", "2001-05-02T13:20:10")]), 3, &mut lost);
+        assert_eq!(frames.len(), 3);
+
+        let frames = from_tdrc(&dates(&[("TDRC", "2001-05")]), 3, &mut lost);
+        assert_eq!(texts(&frames), vec!["TYER \"2001\""]);
+
+        let frames = from_tdrc(&dates(&[("TYER", "1999"), ("TIME", "0101")]), 2, &mut lost);
+        assert_eq!(texts(&frames), vec!["TYER \"1999\"", "TIME \"0101\""]);
+
+        assert_eq!(lost,
+                   vec!["TDRC \"2001-05-02T13:20:10\": partly", "TDRC \"2001-05\": partly"]);
+    }
+
+    #[test]
+    fn round_trip_v23_v24() {
+        let v23 = vec![text("TIT2", "Title", 3),
+                       text("TYER", "2001", 3),
+                       text("TDAT", "0205", 3),
+                       text("TIME", "1320", 3),
+                       text("TORY", "1999", 3),
+                       FrameBody::IPLS(IPLS {
+                           text_encoding: TextEncoding::ISO88591,
+                           people_list_strings: "producer\0Bob".to_string(),
+                       }),
+                       text("COMM", "김광석", 3)];
+
+        let (v24, lost) = convert_frames(frames(v23.clone()), 4);
+        assert!(lost.is_empty());
+        assert_eq!(texts(&bodies(&v24)),
+                   vec!["TIT2 \"Title\"",
+                        "TDRC \"2001-05-02T13:20\"",
+                        "TDOR \"1999\"",
+                        "TIPL \"producer\0Bob\"",
+                        "COMM \"김광석\""]);
+
+        let (back, lost) = convert_frames(v24, 3);
+        assert!(lost.is_empty());
+        assert_eq!(bodies(&back), v23);
+    }
+
+    #[test]
+    fn v24_only_frames() {
+        let v24 = vec![text("TIT2", "Title", 4),
+                       text("TMOO", "Happy", 4),
+                       text("TSOP", "Beatles, The", 4),
+                       text("TDOR", "1999-01-01", 4)];
+
+        let (v23, lost) = convert_frames(frames(v24), 3);
+
+        assert_eq!(texts(&bodies(&v23)), vec!["TIT2 \"Title\"", "TORY \"1999\""]);
+        assert_eq!(lost,
+                   vec!["TMOO \"Happy\": no frame in version 3",
+                        "TSOP \"Beatles, The\": no frame in version 3",
+                        "TDOR \"1999-01-01\": partly"]);
+
+        //
+        // UTF-8 is only for version 4.
+        //
+        assert!(v23.iter().all(|vf| tag::encoding(&vf.body) != Some(&TextEncoding::UTF8)));
+    }
+}
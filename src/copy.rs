@@ -0,0 +1,309 @@
+use artwork;
+use clap;
+use convert;
+use rtag::frame::{FrameBody, framebody_to_id};
+use tag;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use {All, ViewFrame, ViewHead, load, update};
+
+//
+// Frame ids given to `--include`, `--exclude`. ex) "APIC,TXXX:CATALOGNUMBER,COMM"
+//
+struct Filter {
+    include: Option<Vec<String>>,
+    exclude: Vec<String>,
+}
+
+impl Filter {
+    //
+    // `TXXX` is for all TXXX frames, `TXXX:DESC` is for a TXXX frame of the description.
+    //
+    fn pass(&self, fbody: &FrameBody) -> bool {
+        let id = framebody_to_id(fbody, 4);
+        let key = match tag::text_of(fbody) {
+            Some((key, _)) => key,
+            None => id.to_string(),
+        };
+        let hit = |ids: &Vec<String>| ids.iter().any(|i| i == id || *i == key);
+
+        let included = match self.include {
+            Some(ref include) => hit(include),
+            None => true,
+        };
+
+        included && !hit(&self.exclude)
+    }
+}
+
+fn ids(value: Option<&str>) -> Option<Vec<String>> {
+    value.map(|value| {
+        value.split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect()
+    })
+}
+
+//
+// Frames of `source` that pass the filter, and frames of `target` that don't.
+// without a filter, the tag of `target` becomes the same to `source`.
+// a version of `target` is kept, and frames of `source` are converted to it.
+//
+fn transplant(source: &All, target: &Path, filter: &Filter) -> Option<All> {
+    let mut target = load(target)?;
+    let mut frames = Vec::new();
+
+    if target.head.is_none() {
+        target.head = source.head.as_ref().map(|head| {
+            ViewHead {
+                version: head.version.clone(),
+                flags: head.flags.clone(),
+            }
+        });
+    }
+
+    let version = |all: &All| match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => 4,
+    };
+
+    if let Some(ref source_frames) = source.frames {
+        let copied = source_frames.iter()
+            .filter(|vf| filter.pass(&vf.body))
+            .map(|vf| {
+                ViewFrame {
+                    flags: vf.flags.clone(),
+                    body: vf.body.clone(),
+                    picture: None,
+                    resource: None,
+                    genre: None,
+                }
+            })
+            .collect();
+
+        let to = version(&target);
+        if version(source) == to {
+            frames = copied;
+        } else {
+            let (converted, lost) = convert::convert_frames(copied, to);
+            for lost in lost {
+                warn!("Not copied to {}: {}", target.file, lost);
+            }
+            frames = converted;
+        }
+    }
+
+    if let Some(target_frames) = target.frames.take() {
+        frames.extend(target_frames.into_iter().filter(|vf| !filter.pass(&vf.body)));
+    }
+
+    target.frames = if frames.is_empty() { None } else { Some(frames) };
+
+    if filter.include.is_none() && source.frame1.is_some() {
+        target.frame1 = source.frame1.clone();
+    } else {
+        target.frame1 = None;
+    }
+
+    Some(target)
+}
+
+pub fn files_of(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|ext| ext.to_str())
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+                })
+                .collect()
+        }
+        Err(e) => {
+            error!("Can not read a directory: {:?}, {:?}", dir, e);
+            Vec::new()
+        }
+    };
+
+    files.sort();
+    files
+}
+
+//
+// "3/12" => 3. if there is no TRCK, a number in front of a file name is used. "03 - Title.mp3" => 3
+//
+fn track_of(file: &Path) -> Option<String> {
+    fn number(value: &str) -> Option<u32> {
+        let digits: String = value.trim()
+            .chars()
+            .take_while(|ch| ch.is_ascii_digit())
+            .collect();
+        digits.parse().ok()
+    }
+
+    let track = tag::texts(file).and_then(|texts| texts.get("TRCK").and_then(|v| number(v)));
+
+    track.or_else(|| file.file_stem().and_then(|stem| stem.to_str()).and_then(number))
+        .map(|track| track.to_string())
+}
+
+//
+// "03 - Rock 'N' Roll.mp3" => "rocknroll". None if there is only a number. ex) "03.mp3"
+//
+fn name_of(file: &Path) -> Option<String> {
+    let stem = file.file_stem().and_then(|stem| stem.to_str())?;
+    let stem = stem.trim_start_matches(|ch: char| ch.is_ascii_digit() || !ch.is_alphanumeric());
+    let name = stem.chars().filter(|ch| ch.is_alphanumeric()).collect::<String>().to_lowercase();
+
+    if name.is_empty() { None } else { Some(name) }
+}
+
+fn pair(source_dir: &Path, target_dir: &Path, by: &str) -> Vec<(PathBuf, PathBuf)> {
+    let key_of = |file: &Path| match by {
+        "name" => name_of(file),
+        _ => track_of(file),
+    };
+
+    let mut sources = HashMap::new();
+    for file in files_of(source_dir) {
+        match key_of(&file) {
+            Some(key) => {
+                if let Some(dup) = sources.insert(key.clone(), file.clone()) {
+                    warn!("Duplicated {} {}: {:?}, {:?}", by, key, dup, file);
+                }
+            }
+            None => warn!("No {}: {:?}", by, file),
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for file in files_of(target_dir) {
+        match key_of(&file).and_then(|key| sources.remove(&key)) {
+            Some(source) => pairs.push((source, file)),
+            None => warn!("No source for: {:?}", file),
+        }
+    }
+
+    for file in sources.values() {
+        warn!("No target for: {:?}", file);
+    }
+
+    pairs
+}
+
+pub fn copy_tags(matches: clap::ArgMatches) {
+    let targets: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let source = PathBuf::from(matches.value_of("copy-tags").unwrap());
+    let dry_run = matches.is_present("dry-run");
+    let by = matches.value_of("pair-by").unwrap_or("track");
+
+    let filter = Filter {
+        include: ids(matches.value_of("include")),
+        exclude: ids(matches.value_of("exclude")).unwrap_or_default(),
+    };
+
+    let pairs: Vec<(PathBuf, PathBuf)> = if source.is_dir() {
+        targets.iter()
+            .flat_map(|target| pair(&source, Path::new(target), by))
+            .collect()
+    } else {
+        targets.iter().map(|target| (source.clone(), PathBuf::from(target))).collect()
+    };
+
+    let mut loaded: Option<All> = None;
+
+    for (source, target) in pairs {
+        if source == target {
+            continue;
+        }
+
+        if loaded.as_ref().map(|all| all.file.as_str()) != source.to_str() {
+            loaded = load(&source);
+        }
+
+        let source_all = match loaded {
+            Some(ref all) => all,
+            None => continue,
+        };
+
+        println!("{} -> {}", source.display(), target.display());
+
+        if dry_run {
+            continue;
+        }
+
+        let mut all = match transplant(source_all, &target, &filter) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", target, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::tagged;
+
+    fn dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("markdang-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    fn names(pairs: &[(PathBuf, PathBuf)]) -> Vec<(String, String)> {
+        let name = |path: &PathBuf| path.file_name().unwrap().to_string_lossy().to_string();
+        pairs.iter().map(|(source, target)| (name(source), name(target))).collect()
+    }
+
+    #[test]
+    fn track_and_name() {
+        assert_eq!(track_of(Path::new("/nonexistent/03 - Title.mp3")), Some("3".to_string()));
+        assert_eq!(track_of(Path::new("/nonexistent/Title.mp3")), None);
+
+        let path = tagged("copy-track", 3, &[("TRCK", b"\x005/12".to_vec())]);
+        assert_eq!(track_of(&path), Some("5".to_string()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(name_of(Path::new("03 - Rock 'N' Roll.mp3")), Some("rocknroll".to_string()));
+        assert_eq!(name_of(Path::new("Rock N Roll.mp3")), Some("rocknroll".to_string()));
+        assert_eq!(name_of(Path::new("03.mp3")), None);
+    }
+
+    #[test]
+    fn pair_files() {
+        let source = dir("copy-source",
+                         &["01 - Stand Up.mp3", "02 - Holy Diver.mp3", "03 - Gypsy.mp3", "cover.jpg"]);
+        let target = dir("copy-target", &["1 Stand Up.mp3", "02. Holy-Diver.MP3", "04 Caught.mp3"]);
+
+        //
+        // pairs are in order of targets.
+        //
+        for by in &["track", "name"] {
+            assert_eq!(names(&pair(&source, &target, by)),
+                       vec![("02 - Holy Diver.mp3".to_string(), "02. Holy-Diver.MP3".to_string()),
+                            ("01 - Stand Up.mp3".to_string(), "1 Stand Up.mp3".to_string())]);
+        }
+
+        let renamed = dir("copy-renamed", &["10 - Stand Up.mp3"]);
+        assert!(pair(&source, &renamed, "track").is_empty());
+        assert_eq!(pair(&source, &renamed, "name").len(), 1);
+        fs::remove_dir_all(&renamed).unwrap();
+
+        fs::remove_dir_all(&source).unwrap();
+        fs::remove_dir_all(&target).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use audio;
+use checksum;
+use clap;
+use tag;
+
+use rtag::frame::id;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use load;
+
+//
+// Seconds that durations of a song can differ by.
+//
+const DURATION_TOLERANCE: f64 = 2.0;
+
+struct Song {
+    path: PathBuf,
+    artist: String,
+    title: String,
+    duration: f64,
+    bitrate: u32,
+    hash: String,
+    missing: Vec<&'static str>,
+}
+
+impl Song {
+    fn new(path: PathBuf, hash: bool) -> Option<Song> {
+        let all = load(&path)?;
+        let bytes = audio::read(&path)?;
+
+        let info = match audio::info(&bytes) {
+            Some(info) => info,
+            None => {
+                warn!("No audio: {:?}", path);
+                return None;
+            }
+        };
+
+        let mut texts = BTreeMap::new();
+        let mut fbodies = Vec::new();
+
+        if let Some(ref frames) = all.frames {
+            for vf in frames {
+                if let Some((id, text)) = tag::text_of(&vf.body) {
+                    texts.entry(id).or_insert(text);
+                }
+                fbodies.push(&vf.body);
+            }
+        }
+
+        let hash = if hash { checksum::of(&bytes) } else { String::new() };
+        let text = |id: &str| texts.get(id).cloned().unwrap_or_default();
+
+        Some(Song {
+            artist: if texts.contains_key(id::TPE1) { text(id::TPE1) } else { text(id::TPE2) },
+            title: text(id::TIT2),
+            duration: info.duration,
+            bitrate: info.bitrate,
+            hash,
+            missing: tag::missing_essentials(&fbodies),
+            path,
+        })
+    }
+
+    //
+    // "The Beatles", "the beatles " => "thebeatles"
+    //
+    fn key(&self) -> Option<(String, String)> {
+        let normalize = |value: &str| {
+            value.chars().filter(|ch| ch.is_alphanumeric()).collect::<String>().to_lowercase()
+        };
+
+        let artist = normalize(&self.artist);
+        let title = normalize(&self.title);
+
+        if artist.is_empty() || title.is_empty() {
+            None
+        } else {
+            Some((artist, title))
+        }
+    }
+}
+
+//
+// Songs of the same artist and title are split where durations differ more than the tolerance.
+//
+fn by_tag(songs: Vec<Song>) -> Vec<Vec<Song>> {
+    let mut by_key: BTreeMap<(String, String), Vec<Song>> = BTreeMap::new();
+
+    for song in songs {
+        match song.key() {
+            Some(key) => by_key.entry(key).or_default().push(song),
+            None => debug!("no artist or title: {:?}", song.path),
+        }
+    }
+
+    let mut groups = Vec::new();
+
+    for mut songs in by_key.into_values() {
+        songs.sort_by(|a, b| a.duration.partial_cmp(&b.duration).unwrap());
+
+        let mut group: Vec<Song> = Vec::new();
+        for song in songs {
+            let near = |last: &Song| song.duration - last.duration <= DURATION_TOLERANCE;
+            if !group.last().is_none_or(near) {
+                groups.push(group);
+                group = Vec::new();
+            }
+            group.push(song);
+        }
+        groups.push(group);
+    }
+
+    groups
+}
+
+fn by_hash(songs: Vec<Song>) -> Vec<Vec<Song>> {
+    let mut by_hash: BTreeMap<String, Vec<Song>> = BTreeMap::new();
+
+    for song in songs {
+        by_hash.entry(song.hash.clone()).or_default().push(song);
+    }
+
+    by_hash.into_values().collect()
+}
+
+pub fn duplicates(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let by = matches.value_of("duplicates").unwrap();
+
+    let hash = match by {
+        "tag" => false,
+        "hash" => true,
+        _ => {
+            error!("Unknown duplicates: {}. (tag|hash)", by);
+            return;
+        }
+    };
+
+    let mut songs = Vec::new();
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        songs.extend(Song::new(path, hash));
+    }
+
+    let groups = if hash { by_hash(songs) } else { by_tag(songs) };
+
+    for group in groups.into_iter().filter(|group| group.len() > 1) {
+        let first = &group[0];
+
+        if hash {
+            println!("{} - {} (md5 {})", first.artist, first.title, first.hash);
+        } else {
+            println!("{} - {} ({})", first.artist, first.title, audio::minutes(first.duration));
+        }
+
+        for song in &group {
+            let missing = if song.missing.is_empty() {
+                String::new()
+            } else {
+                format!(", missing {}", song.missing.join(","))
+            };
+
+            println!("\t{}\t{} kbps\t{}\ttags {}/{}{}",
+                     song.path.display(),
+                     song.bitrate,
+                     audio::minutes(song.duration),
+                     tag::ESSENTIALS.len() - song.missing.len(),
+                     tag::ESSENTIALS.len(),
+                     missing);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(name: &str, artist: &str, title: &str, duration: f64, hash: &str) -> Song {
+        Song {
+            path: PathBuf::from(name),
+            artist: artist.to_string(),
+            title: title.to_string(),
+            duration,
+            bitrate: 128,
+            hash: hash.to_string(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn names(groups: &[Vec<Song>]) -> Vec<Vec<String>> {
+        groups.iter()
+            .map(|group| group.iter().map(|song| song.path.display().to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn duplicates_by_tag() {
+        let songs = vec![song("a", "The Beatles", "Let It Be", 243.0, ""),
+                         song("b", "the beatles ", "let it be", 241.0, ""),
+                         song("c", "The Beatles", "Let It Be", 250.5, ""),
+                         song("d", "The Beatles", "", 243.0, ""),
+                         song("e", "Dio", "Holy Diver", 100.0, "")];
+
+        assert_eq!(names(&by_tag(songs)),
+                   vec![vec!["e"], vec!["b", "a"], vec!["c"]]);
+    }
+
+    #[test]
+    fn tolerance_of_duration() {
+        let songs = vec![song("a", "A", "T", 100.0, ""),
+                         song("b", "A", "T", 102.0, ""),
+                         song("c", "A", "T", 104.01, "")];
+
+        assert_eq!(names(&by_tag(songs)), vec![vec!["a", "b"], vec!["c"]]);
+    }
+
+    #[test]
+    fn duplicates_by_hash() {
+        let songs = vec![song("a", "A", "T", 100.0, "x"),
+                         song("b", "B", "U", 200.0, "y"),
+                         song("c", "", "", 300.0, "x")];
+
+        assert_eq!(names(&by_hash(songs)), vec![vec!["a", "c"], vec!["b"]]);
+    }
+}
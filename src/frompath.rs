@@ -0,0 +1,248 @@
+use artwork;
+use clap;
+use regex::{self, Regex};
+use resource;
+use serde_json;
+
+use std::path::{Path, PathBuf};
+
+use {fill, load, update};
+
+//
+// A pattern is a template or a regular expression.
+// - template: "{TPE1}/{TDRC} - {TALB}/{TRCK} - {TIT2}", `{*}` matches anything and is ignored
+// - regular expression: "(?P<TPE1>[^/]+)/(?P<TALB>[^/]+)/(?P<TRCK>\d+) (?P<TIT2>[^/]+)$"
+// both of them match the end of a path without an extension.
+//
+struct Pattern {
+    re: Regex,
+    ids: Vec<(String, String)>, // (group name, frame id)
+}
+
+fn parse(pattern: &str) -> Option<Pattern> {
+    if pattern.contains("(?P<") {
+        let re = match Regex::new(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                error!("Invalid pattern: {}, {:?}", pattern, e);
+                return None;
+            }
+        };
+
+        let ids = re.capture_names()
+            .flatten()
+            .map(|name| (name.to_string(), name.to_string()))
+            .collect();
+
+        return Some(Pattern { re, ids });
+    }
+
+    let mut expr = String::from("(?:^|/)");
+    let mut ids = Vec::new();
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => {
+                expr.push_str(&regex::escape(&text));
+                text.clear();
+
+                let id: String = chars.by_ref().take_while(|ch| *ch != '}').collect();
+                let id = id.trim();
+
+                if id == "*" {
+                    expr.push_str("[^/]*?");
+                } else {
+                    let name = format!("f{}", ids.len());
+                    expr.push_str(&format!("(?P<{}>[^/]+?)", name));
+                    ids.push((name, id.to_string()));
+                }
+            }
+            '\\' => text.push('/'),
+            _ => text.push(ch),
+        }
+    }
+
+    expr.push_str(&regex::escape(&text));
+    expr.push('$');
+
+    debug!("pattern: {}", expr);
+
+    match Regex::new(&expr) {
+        Ok(re) => Some(Pattern { re, ids }),
+        Err(e) => {
+            error!("Invalid pattern: {}, {:?}", pattern, e);
+            None
+        }
+    }
+}
+
+//
+// (frame id, value) from a path. "Artist/2001 - Album/03 - Title.mp3" => [(TPE1, Artist), ..]
+//
+fn captures(pattern: &Pattern, path: &Path) -> Option<Vec<(String, String)>> {
+    let path = path.with_extension("");
+    let path = path.to_string_lossy().replace('\\', "/");
+
+    let caps = pattern.re.captures(&path)?;
+
+    Some(pattern.ids
+        .iter()
+        .filter_map(|(name, id)| {
+            caps.name(name).map(|m| (id.clone(), m.as_str().trim().to_string()))
+        })
+        .filter(|(_, value)| !value.is_empty())
+        .collect())
+}
+
+pub fn from_path(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    let pattern = match parse(matches.value_of("from-path").unwrap()) {
+        Some(pattern) => pattern,
+        None => return,
+    };
+
+    let dump = match matches.value_of("binary") {
+        Some("file") => {
+            resource::Dump::Dir(PathBuf::from(matches.value_of("binary-dir").unwrap_or(".")))
+        }
+        _ => resource::Dump::Base64,
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let values = match captures(&pattern, &path) {
+            Some(values) => values,
+            None => {
+                warn!("Pattern does not match: {:?}", path);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let version = match all.head {
+            Some(ref head) => head.version.parse().unwrap_or(4),
+            None => 4,
+        };
+
+        let merged: Vec<_> = values.iter()
+            .filter(|&(id, value)| fill(&mut all, id, value, version))
+            .collect();
+
+        if merged.is_empty() {
+            debug!("nothing to write: {:?}", path);
+            continue;
+        }
+
+        if dry_run {
+            //
+            // binary properties are taken out to resources, so the block can be given to `-w`.
+            //
+            if let Some(ref mut frames) = all.frames {
+                for vf in frames.iter_mut() {
+                    vf.resource = resource::dump(&mut vf.body, &dump);
+                }
+            }
+
+            match serde_json::to_string_pretty(&all) {
+                Ok(json_str) => {
+                    println!("//<");
+                    println!("{}", json_str);
+                    println!("//>");
+                }
+                Err(e) => error!("Can not serialize: {:?}, {:?}", path, e),
+            }
+            continue;
+        }
+
+        println!("{}", path.display());
+        for (id, value) in merged {
+            println!("\t{}: {}", id, value);
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        captures(&parse(pattern).unwrap(), Path::new(path))
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(pairs.iter().map(|&(id, value)| (id.to_string(), value.to_string())).collect())
+    }
+
+    #[test]
+    fn template() {
+        assert_eq!(values("{TPE1}/{TDRC} - {TALB}/{TRCK} - {TIT2}",
+                          "/music/Dio/1985 - Sacred Heart/04 - Rock 'N' Roll Children.mp3"),
+                   pairs(&[("TPE1", "Dio"),
+                           ("TDRC", "1985"),
+                           ("TALB", "Sacred Heart"),
+                           ("TRCK", "04"),
+                           ("TIT2", "Rock 'N' Roll Children")]));
+
+        assert_eq!(values("{TRCK}. {TIT2} (Live)", "/music/01. Holy Diver (Live).mp3"),
+                   pairs(&[("TRCK", "01"), ("TIT2", "Holy Diver")]));
+
+        assert_eq!(values("{TPE1}\\{TIT2}", "/music/Dio/Holy Diver.mp3"),
+                   pairs(&[("TPE1", "Dio"), ("TIT2", "Holy Diver")]));
+
+        assert_eq!(values("{TRCK} - {TIT2}", "/music/Holy Diver.mp3"), None);
+    }
+
+    #[test]
+    fn nested_directories() {
+        //
+        // a value is in a segment, and a pattern matches the end of a path.
+        //
+        assert_eq!(values("{TALB}/{TRCK} {TIT2}", "/music/Dio/Holy Diver/01 Stand Up.mp3"),
+                   pairs(&[("TALB", "Holy Diver"), ("TRCK", "01"), ("TIT2", "Stand Up")]));
+
+        assert_eq!(values("{TPE1}/{*}/{TIT2}", "/music/Dio/Holy Diver/Stand Up.mp3"),
+                   pairs(&[("TPE1", "Dio"), ("TIT2", "Stand Up")]));
+
+        assert_eq!(values("{TPE1}/{TIT2}", "/music/Dio/Holy Diver/Stand Up.mp3"),
+                   pairs(&[("TPE1", "Holy Diver"), ("TIT2", "Stand Up")]));
+
+        assert_eq!(values("{TPE1}/{TALB}/{TIT2}", "Stand Up.mp3"), None);
+    }
+
+    #[test]
+    fn regular_expression() {
+        let pattern = r"(?P<TPE1>[^/]+)/(?:(?P<TALB>[^/]+) - )?(?P<TIT2>[^/]+)$";
+
+        assert_eq!(values(pattern, "/music/Dio/Holy Diver - Stand Up.mp3"),
+                   pairs(&[("TPE1", "Dio"), ("TALB", "Holy Diver"), ("TIT2", "Stand Up")]));
+
+        //
+        // a group that does not take part is skipped, and so is an empty one.
+        //
+        assert_eq!(values(pattern, "/music/Dio/Stand Up.mp3"),
+                   pairs(&[("TPE1", "Dio"), ("TIT2", "Stand Up")]));
+        assert_eq!(values(r"(?P<TRCK>\d*) ?(?P<TIT2>[^/]+)$", "/music/Stand Up.mp3"),
+                   pairs(&[("TIT2", "Stand Up")]));
+
+        assert!(parse("(?P<TIT2>[^/]+").is_none());
+    }
+}
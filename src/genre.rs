@@ -0,0 +1,341 @@
+use artwork;
+use clap;
+use serde_json;
+use tag;
+
+use rtag::frame::FrameBody;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use {All, load, update};
+
+//
+// ID3v1 genres and Winamp extensions. an index is the genre byte of frame1.
+//
+const GENRES: [&str; 192] =
+    ["Blues", "Classic Rock", "Country", "Dance", "Disco", "Funk", "Grunge", "Hip-Hop", "Jazz",
+     "Metal", "New Age", "Oldies", "Other", "Pop", "R&B", "Rap", "Reggae", "Rock", "Techno",
+     "Industrial", "Alternative", "Ska", "Death Metal", "Pranks", "Soundtrack", "Euro-Techno",
+     "Ambient", "Trip-Hop", "Vocal", "Jazz+Funk", "Fusion", "Trance", "Classical", "Instrumental",
+     "Acid", "House", "Game", "Sound Clip", "Gospel", "Noise", "AlternRock", "Bass", "Soul",
+     "Punk", "Space", "Meditative", "Instrumental Pop", "Instrumental Rock", "Ethnic", "Gothic",
+     "Darkwave", "Techno-Industrial", "Electronic", "Pop-Folk", "Eurodance", "Dream",
+     "Southern Rock", "Comedy", "Cult", "Gangsta", "Top 40", "Christian Rap", "Pop/Funk", "Jungle",
+     "Native American", "Cabaret", "New Wave", "Psychadelic", "Rave", "Showtunes", "Trailer",
+     "Lo-Fi", "Tribal", "Acid Punk", "Acid Jazz", "Polka", "Retro", "Musical", "Rock & Roll",
+     "Hard Rock", "Folk", "Folk-Rock", "National Folk", "Swing", "Fast Fusion", "Bebob", "Latin",
+     "Revival", "Celtic", "Bluegrass", "Avantgarde", "Gothic Rock", "Progressive Rock",
+     "Psychedelic Rock", "Symphonic Rock", "Slow Rock", "Big Band", "Chorus", "Easy Listening",
+     "Acoustic", "Humour", "Speech", "Chanson", "Opera", "Chamber Music", "Sonata", "Symphony",
+     "Booty Bass", "Primus", "Porn Groove", "Satire", "Slow Jam", "Club", "Tango", "Samba",
+     "Folklore", "Ballad", "Power Ballad", "Rhythmic Soul", "Freestyle", "Duet", "Punk Rock",
+     "Drum Solo", "A capella", "Euro-House", "Dance Hall", "Goa", "Drum & Bass", "Club-House",
+     "Hardcore", "Terror", "Indie", "BritPop", "Negerpunk", "Polsk Punk", "Beat",
+     "Christian Gangsta Rap", "Heavy Metal", "Black Metal", "Crossover", "Contemporary Christian",
+     "Christian Rock", "Merengue", "Salsa", "Thrash Metal", "Anime", "JPop", "Synthpop",
+     "Abstract", "Art Rock", "Baroque", "Bhangra", "Big Beat", "Breakbeat", "Chillout",
+     "Downtempo", "Dub", "EBM", "Eclectic", "Electro", "Electroclash", "Emo", "Experimental",
+     "Garage", "Global", "IDM", "Illbient", "Industro-Goth", "Jam Band", "Krautrock", "Leftfield",
+     "Lounge", "Math Rock", "New Romantic", "Nu-Breakz", "Post-Punk", "Post-Rock", "Psytrance",
+     "Shoegaze", "Space Rock", "Trop Rock", "World Music", "Neoclassical", "Audiobook",
+     "Audio Theatre", "Neue Deutsche Welle", "Podcast", "Indie Rock", "G-Funk", "Dubstep",
+     "Garage Rock", "Psybient"];
+
+//
+// frame1 has 255 if there is no genre.
+//
+pub const NONE: u8 = 255;
+
+pub fn name(index: u8) -> Option<&'static str> {
+    GENRES.get(index as usize).cloned()
+}
+
+//
+// An index from a name or a TCON value. ex) "Rock", "rock", "17", "(17)", "(17)Rock" => 17
+//
+pub fn index(value: &str) -> Option<u8> {
+    let value = value.trim();
+
+    let number = value.trim_start_matches('(');
+    let digits: String = number.chars().take_while(|ch| ch.is_ascii_digit()).collect();
+    let rest = &number[digits.len()..];
+
+    if !digits.is_empty() && (rest.is_empty() || rest.starts_with(')')) {
+        return digits.parse().ok().filter(|index| name(*index).is_some());
+    }
+
+    find(value).and_then(|name| GENRES.iter().position(|genre| *genre == name)).map(|index| index as u8)
+}
+
+//
+// "hip hop", "HipHop" => "Hip-Hop"
+//
+pub fn find(value: &str) -> Option<&'static str> {
+    let value = key(value);
+
+    GENRES.iter().find(|genre| key(genre) == value).cloned()
+}
+
+//
+// A lower case of letters and digits to compare names.
+//
+pub fn key(value: &str) -> String {
+    value.chars().filter(|ch| ch.is_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+//
+// The name of a genre byte of frame1. None for 255 or an unknown one.
+//
+pub fn from_frame1(genre: &str) -> Option<&'static str> {
+    genre.trim().parse().ok().and_then(name)
+}
+
+fn reference(value: &str) -> String {
+    match value {
+        "RX" => "Remix".to_string(),
+        "CR" => "Cover".to_string(),
+        _ => {
+            match value.parse().ok().and_then(name) {
+                Some(name) => name.to_string(),
+                None => value.to_string(),
+            }
+        }
+    }
+}
+
+//
+// Genres of a TCON value.
+// - version 3: "(17)" => ["Rock"], "(17)Rock & Roll" => ["Rock & Roll"], "(17)(18)" => ["Rock", "Techno"]
+//   a text after references is a refinement of the last one. "((" is a "(" of a text.
+// - version 4: genres are separated by null. "17\0Acid" => ["Rock", "Acid"]
+// - "17" => ["Rock"], "Rock" => ["Rock"], "Rock; Acid" => ["Rock", "Acid"]
+//
+pub fn names(value: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+
+    for part in value.split(['\0', ';']) {
+        let mut rest = part.trim();
+        let mut refs = 0;
+
+        while rest.starts_with('(') && !rest.starts_with("((") {
+            let close = match rest.find(')') {
+                Some(close) => close,
+                None => break,
+            };
+            ret.push(reference(&rest[1..close]));
+            refs += 1;
+            rest = rest[close + 1..].trim_start();
+        }
+
+        let rest = rest.replacen("((", "(", 1);
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        if refs > 0 {
+            ret.pop();
+            ret.push(rest);
+        } else {
+            ret.push(reference(&rest));
+        }
+    }
+
+    ret
+}
+
+//
+// A TCON value to show. genres are joined with "; ". ex) "(17)(18)" => "Rock; Techno"
+//
+pub fn resolve(value: &str) -> String {
+    names(value).join("; ")
+}
+
+//
+// Names of a TCON frame to show.
+//
+pub fn tcon(fbody: &FrameBody) -> Option<String> {
+    match *fbody {
+        FrameBody::TCON(ref frame) => Some(resolve(&frame.text)),
+        _ => None,
+    }
+}
+
+//
+// An alias map from a json object. ex) {"hiphop": "Hip-Hop", "kpop": "K-Pop"}
+// keys are compared with `key`.
+//
+fn aliases(file: &str) -> Option<HashMap<String, String>> {
+    let fs = match File::open(file) {
+        Ok(fs) => fs,
+        Err(e) => {
+            error!("Can not open an alias file: {}, {:?}", file, e);
+            return None;
+        }
+    };
+
+    let map: HashMap<String, String> = match serde_json::from_reader(fs) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Invalid alias file: {}, {:?}", file, e);
+            return None;
+        }
+    };
+
+    Some(map.into_iter().map(|(k, v)| (key(&k), v)).collect())
+}
+
+//
+// An alias, a spelling of the genre list or a trimmed text.
+//
+fn canonical(name: &str, aliases: &HashMap<String, String>) -> String {
+    match aliases.get(&key(name)) {
+        Some(alias) => alias.clone(),
+        None => find(name).map(|name| name.to_string()).unwrap_or_else(|| name.trim().to_string()),
+    }
+}
+
+fn canonical_names(value: &str, aliases: &HashMap<String, String>) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+
+    for name in names(value) {
+        let name = canonical(&name, aliases);
+        if !name.is_empty() && !ret.iter().any(|n| key(n) == key(&name)) {
+            ret.push(name);
+        }
+    }
+
+    ret
+}
+
+//
+// (changed, report)
+//
+fn normalize_all(all: &mut All, aliases: &HashMap<String, String>) -> (bool, Vec<String>) {
+    let mut report = Vec::new();
+    let mut first = None;
+
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => 4,
+    };
+
+    if let Some(ref mut frames) = all.frames {
+        for vf in frames.iter_mut() {
+            let value = match vf.body {
+                FrameBody::TCON(ref frame) => frame.text.clone(),
+                _ => continue,
+            };
+
+            //
+            // names are separated by null in version 4, and by "; " in others
+            // because a reference of version 3 can not be refined by more than one text.
+            //
+            let names = canonical_names(&value, aliases);
+            let normalized = names.join(if version == 4 { "\0" } else { "; " });
+            first = first.or_else(|| names.into_iter().next());
+
+            if normalized == value || normalized.is_empty() {
+                continue;
+            }
+
+            if let Some(fbody) = tag::text_frame("TCON", &normalized, version) {
+                report.push(format!("TCON: {:?} -> {:?}", value, normalized));
+                vf.body = fbody;
+            }
+        }
+    }
+
+    if let Some(ref mut frame1) = all.frame1 {
+        let index = first.as_ref().and_then(|name| index(name));
+        let old: Option<u8> = frame1.genre.trim().parse().ok();
+
+        if let Some(index) = index.filter(|index| Some(*index) != old) {
+            report.push(format!("frame1 genre: {:?} -> {:?}", frame1.genre, self::name(index).unwrap()));
+            frame1.genre = index.to_string();
+        }
+    }
+
+    (!report.is_empty(), report)
+}
+
+pub fn normalize(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    let aliases = match matches.value_of("genre-alias") {
+        Some(file) => {
+            match aliases(file) {
+                Some(aliases) => aliases,
+                None => return,
+            }
+        }
+        None => HashMap::new(),
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let (changed, report) = normalize_all(&mut all, &aliases);
+
+        if report.is_empty() {
+            debug!("genre is already normalized: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for line in report {
+            println!("\t{}", line);
+        }
+
+        if dry_run || !changed {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_of_names_and_references() {
+        assert_eq!(index("Rock"), Some(17));
+        assert_eq!(index(" rock "), Some(17));
+        assert_eq!(index("hip hop"), Some(7));
+        assert_eq!(index("17"), Some(17));
+        assert_eq!(index("(17)"), Some(17));
+        assert_eq!(index("(17)Rock"), Some(17));
+        assert_eq!(index("192"), None);
+        assert_eq!(index("Unknown Genre"), None);
+    }
+
+    #[test]
+    fn names_of_tcon() {
+        assert_eq!(names("(17)"), vec!["Rock"]);
+        assert_eq!(names("(17)(18)"), vec!["Rock", "Techno"]);
+        assert_eq!(names("(17)Rock & Roll"), vec!["Rock & Roll"]);
+        assert_eq!(names("((Live)"), vec!["(Live)"]);
+        assert_eq!(names("(RX)(CR)"), vec!["Remix", "Cover"]);
+        assert_eq!(names("17\0Acid"), vec!["Rock", "Acid"]);
+        assert_eq!(names("Rock; Acid"), vec!["Rock", "Acid"]);
+        assert_eq!(names(""), Vec::<String>::new());
+    }
+}
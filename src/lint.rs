@@ -0,0 +1,293 @@
+use artwork;
+use audio;
+use clap;
+use serde_json;
+use tag;
+
+use rtag::frame::{FrameBody, framebody_to_id};
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::process;
+
+use {All, load};
+
+//
+// Rules of a json file. a rule that is not given is not checked. ex)
+// {
+//   "required": ["TIT2", "TPE1", "TALB", "TRCK"],
+//   "forbidden": ["PRIV", "TXXX:Encoded by"],
+//   "encodings": ["ISO88591", "UTF16LE"],
+//   "max_artwork_bytes": 524288,
+//   "max_artwork_dimension": 1000,
+//   "versions": [3, 4],
+//   "no_v1": true,
+//   "whitespace": true
+// }
+//
+#[derive(Debug, Deserialize)]
+struct Rules {
+    //
+    // frame ids that must have a value. TDRC is also TYER.
+    //
+    required: Option<Vec<String>>,
+    //
+    // frame ids, or `TXXX:description`
+    //
+    forbidden: Option<Vec<String>>,
+    encodings: Option<Vec<String>>,
+    max_artwork_bytes: Option<usize>,
+    max_artwork_dimension: Option<u32>,
+    //
+    // major versions of ID3v2
+    //
+    versions: Option<Vec<u8>>,
+    no_v1: Option<bool>,
+    //
+    // no leading or trailing whitespace in text
+    //
+    whitespace: Option<bool>,
+}
+
+impl Default for Rules {
+    //
+    // `tag::ESSENTIALS` and no whitespace.
+    //
+    fn default() -> Rules {
+        Rules {
+            required: Some(tag::ESSENTIALS.iter().map(|id| id.to_string()).collect()),
+            forbidden: None,
+            encodings: None,
+            max_artwork_bytes: None,
+            max_artwork_dimension: None,
+            versions: None,
+            no_v1: None,
+            whitespace: Some(true),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Violation {
+    rule: &'static str,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    file: String,
+    violations: Vec<Violation>,
+}
+
+//
+// `default` or a json file.
+//
+fn rules(value: &str) -> Option<Rules> {
+    if value == "default" {
+        return Some(Rules::default());
+    }
+
+    let fs = match File::open(value) {
+        Ok(fs) => fs,
+        Err(e) => {
+            error!("Can not open a rule file: {}, {:?}", value, e);
+            return None;
+        }
+    };
+
+    match serde_json::from_reader(fs) {
+        Ok(rules) => Some(rules),
+        Err(e) => {
+            error!("Invalid rule file: {}, {:?}", value, e);
+            None
+        }
+    }
+}
+
+//
+// Text of a frame in its encoding byte. BOMs are removed.
+//
+fn raw_text(data: &[u8]) -> Option<String> {
+    let (&encoding, text) = data.split_first()?;
+    tag::decode(encoding, text)
+}
+
+fn lint_all(all: &All, bytes: &[u8], rules: &Rules) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violate = |rule: &'static str, message: String| {
+        violations.push(Violation {
+            rule,
+            message,
+        })
+    };
+
+    let fbodies: Vec<&FrameBody> = all.frames.iter().flatten().map(|vf| &vf.body).collect();
+
+    if let Some(ref versions) = rules.versions {
+        match all.head {
+            Some(ref head) => {
+                if !versions.iter().any(|v| v.to_string() == head.version) {
+                    violate("versions", format!("ID3v2.{}", head.version));
+                }
+            }
+            None => violate("versions", "no ID3v2".to_string()),
+        }
+    }
+
+    if rules.no_v1 == Some(true) && all.frame1.is_some() {
+        violate("no_v1", "ID3v1".to_string());
+    }
+
+    if let Some(ref required) = rules.required {
+        for id in required {
+            let found = fbodies.iter().any(|fbody| {
+                let fid = framebody_to_id(fbody, 4);
+                let same = fid == id || (id == "TDRC" && fid == "TYER");
+
+                let empty = match tag::text_of(fbody) {
+                    Some((_, text)) => text.trim_matches(|c: char| c.is_whitespace() || c == '\0').is_empty(),
+                    None => false,
+                };
+
+                same && !empty
+            });
+
+            if !found {
+                violate("required", format!("no {}", id));
+            }
+        }
+    }
+
+    for fbody in &fbodies {
+        let id = framebody_to_id(fbody, 4);
+        let text = tag::text_of(fbody);
+
+        if let Some(ref forbidden) = rules.forbidden {
+            let key = text.as_ref().map(|t| t.0.as_str()).unwrap_or(id);
+            if forbidden.iter().any(|f| f == id || f == key) {
+                violate("forbidden", key.to_string());
+            }
+        }
+
+        if let Some(ref encodings) = rules.encodings {
+            if let Some(encoding) = tag::encoding(fbody) {
+                let encoding = format!("{:?}", encoding);
+                if !encodings.contains(&encoding) {
+                    violate("encodings", format!("{} in {}", id, encoding));
+                }
+            }
+        }
+
+        if let Some(picture) = artwork::Picture::from_framebody(fbody) {
+            if rules.max_artwork_bytes.is_some_and(|max| picture.size > max) {
+                violate("max_artwork_bytes", format!("{} bytes", picture.size));
+            }
+            if rules.max_artwork_dimension.is_some_and(|max| picture.width > max || picture.height > max) {
+                violate("max_artwork_dimension",
+                        format!("{}x{}", picture.width, picture.height));
+            }
+        }
+
+    }
+
+    //
+    // rtag trims text when it reads, so text is read from bytes of frames.
+    //
+    if rules.whitespace == Some(true) {
+        for (id, _, data) in audio::id3v2_frames(bytes) {
+            if !id.starts_with('T') && id != "COMM" {
+                continue;
+            }
+
+            let text = match raw_text(data) {
+                Some(text) => text,
+                None => continue,
+            };
+
+            //
+            // COMM has a language before its description.
+            //
+            let text = if id == "COMM" { text.chars().skip(3).collect() } else { text };
+
+            for value in text.split('\0').filter(|value| !value.is_empty()) {
+                if value != value.trim() {
+                    violate("whitespace", format!("{}: {:?}", id, value));
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+pub fn lint(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let rules = match rules(matches.value_of("lint").unwrap()) {
+        Some(rules) => rules,
+        None => process::exit(2),
+    };
+
+    let mut reports = Vec::new();
+    //
+    // a file that can not be read is not passed.
+    //
+    let mut failed = 0;
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let all = match load(&path) {
+            Some(all) => all,
+            None => {
+                error!("Can not read a tag: {:?}", path);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let bytes = match audio::read(&path) {
+            Some(bytes) => bytes,
+            None => {
+                error!("Can not read: {:?}", path);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let violations = lint_all(&all, &bytes, &rules);
+        if !violations.is_empty() {
+            reports.push(Report {
+                file: path.display().to_string(),
+                violations,
+            });
+        }
+    }
+
+    match matches.value_of("format") {
+        Some("j") | Some("jj") => {
+            match serde_json::to_string_pretty(&reports) {
+                Ok(json) => println!("{}", json),
+                Err(e) => error!("{:?}", e),
+            }
+        }
+        _ => {
+            for report in &reports {
+                println!("{}", report.file);
+                for violation in &report.violations {
+                    println!("\t{}: {}", violation.rule, violation.message);
+                }
+            }
+        }
+    }
+
+    if !reports.is_empty() || failed > 0 {
+        process::exit(1);
+    }
+}
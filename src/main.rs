@@ -1,10 +1,15 @@
+extern crate base64;
 extern crate clap;
+extern crate encoding;
 extern crate env_logger;
 #[macro_use]
 extern crate log;
 extern crate hyper;
+extern crate image;
+extern crate md5;
 extern crate regex;
 extern crate rtag;
+extern crate symphonia;
 extern crate time;
 
 #[macro_use]
@@ -12,7 +17,6 @@ extern crate serde_derive;
 extern crate serde_json;
 
 use clap::App;
-use regex::Regex;
 use rtag::metadata::MetadataReader as Reader;
 use rtag::metadata::MetadataWriter as Writer;
 use rtag::metadata::Unit;
@@ -26,12 +30,50 @@ use std::cell::RefCell;
 use std::path::{PathBuf, Path};
 use std::fmt;
 
+mod album;
+//
+// serde_derive 0.9 puts impls of a derive in a const, that is non-local for
+// the lint. modules that derive them allow it.
+//
+#[allow(non_local_definitions)]
+mod artwork;
+mod audio;
+mod checksum;
+#[allow(non_local_definitions)]
+mod clean;
+mod convert;
+mod copy;
+mod duplicate;
+mod frompath;
+mod genre;
+#[allow(non_local_definitions)]
+mod lint;
+mod mojibake;
+mod number;
+mod organize;
+mod padding;
+mod replaygain;
+mod reencode;
+mod resource;
+#[allow(non_local_definitions)]
+mod stats;
+mod stream;
+mod strip;
+mod sync;
+mod tag;
+mod untag;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct All {
     file: String,
     head: Option<ViewHead>,
     frames: Option<Vec<ViewFrame>>,
     frame1: Option<Frame1>,
+    //
+    // md5 of the audio without tags. see `checksum`
+    //
+    #[serde(skip_serializing_if = "Option::is_none")]
+    audio_md5: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,6 +86,15 @@ struct ViewHead {
 struct ViewFrame {
     flags: Option<Vec<FrameHeaderFlag>>,
     body: FrameBody,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    picture: Option<artwork::Picture>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource: Option<String>,
+    //
+    // names of a TCON value. ex) "(17)(18)" => "Rock; Techno"
+    //
+    #[serde(skip_serializing_if = "Option::is_none")]
+    genre: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -80,6 +131,10 @@ impl fmt::Display for All {
             _ => (),
         };
 
+        if let Some(ref v) = self.audio_md5 {
+            let _ = writeln!(f, "\taudio_md5: {}", v);
+        }
+
         match self.frames {
             Some(ref vv) => {
                 for v in vv {
@@ -87,6 +142,15 @@ impl fmt::Display for All {
                         let _ = write!(f, "\tframe_flags: {:?}\n", v);
                     }
                     let _ = write!(f, "\t{:?}\n", v.body);
+                    if let Some(ref v) = v.picture {
+                        let _ = writeln!(f, "\tpicture: {}", v);
+                    }
+                    if let Some(ref v) = v.resource {
+                        let _ = writeln!(f, "\tresource: {}", v);
+                    }
+                    if let Some(ref v) = v.genre {
+                        let _ = writeln!(f, "\tgenre: {}", v);
+                    }
                 }
             }
             _ => (),
@@ -167,8 +231,8 @@ fn frame1_to_map(frame: &Frame1) -> HashMap<&str, String> {
     if !frame.comment.is_empty() {
         ret.insert("comment", frame.comment.to_string());
     }
-    if !frame.genre.is_empty() {
-        ret.insert("genre", frame.genre.to_string());
+    if let Some(name) = genre::from_frame1(&frame.genre) {
+        ret.insert("genre", name.to_string());
     }
     if !frame.title.is_empty() {
         ret.insert("title", frame.title.to_string());
@@ -196,8 +260,39 @@ fn framebody_to_map<'a>(fbody: &FrameBody) -> HashMap<&'a str, String> {
     m.clone()
 }
 
+fn frame_to_map<'a>(fbody: &FrameBody, picture: &Option<artwork::Picture>) -> HashMap<&'a str, String> {
+    let mut map = framebody_to_map(fbody);
+
+    if let Some(ref picture) = *picture {
+        picture.extend(&mut map);
+    }
+
+    if let Some(codepage) = mojibake::detect(fbody) {
+        map.insert("mojibake", codepage.to_string());
+    }
+
+    if let Some(genre) = genre::tcon(fbody) {
+        map.insert("genre", genre);
+    }
+
+    map
+}
+
+//
+// A picture is decoded and hashed only if `--match` uses its properties. ex) APIC.width<500
+//
+fn picture_of(fbody: &FrameBody, picture: bool) -> Option<artwork::Picture> {
+    if picture {
+        artwork::Picture::from_framebody(fbody)
+    } else {
+        None
+    }
+}
+
 fn simple<'a>(file: &'a Path,
-              match_filter: &Box<Fn(HashMap<String, HashMap<&str, String>>) -> bool>)
+              match_filter: &MatchFilter,
+              stream: bool,
+              picture: bool)
               -> Option<Simple> {
     let reader = Reader::new(file.to_str().unwrap());
 
@@ -213,15 +308,18 @@ fn simple<'a>(file: &'a Path,
     };
 
     let mut bodies: HashMap<String, HashMap<&str, String>> = HashMap::new();
+    let mut strings = tag::Strings::read(file);
 
     for unit in reader.unwrap() {
         match unit {
-            Unit::Header(head) => {
+            Unit::Header(head) if head.tag_id == "ID3" => {
                 simple.version = Some(head.version.to_string());
             }
-            Unit::FrameV2(ref fhead, ref fbody) => {
+            Unit::FrameV2(ref fhead, fbody) => {
+                let fbody = &strings.fix(fbody);
 
-                bodies.insert(fhead.id(), framebody_to_map(fbody));
+                bodies.insert(fhead.id(),
+                              frame_to_map(fbody, &picture_of(fbody, picture)));
 
                 if simple.frames.is_none() {
                     simple.frames = Some(vec![]);
@@ -241,6 +339,10 @@ fn simple<'a>(file: &'a Path,
         }
     }
 
+    if stream {
+        bodies.insert("MPEG".to_string(), stream::to_map(file));
+    }
+
     if match_filter(bodies) {
         Some(simple)
     } else {
@@ -250,7 +352,9 @@ fn simple<'a>(file: &'a Path,
 }
 
 fn basic<'a>(file: &'a Path,
-             match_filter: &Box<Fn(HashMap<String, HashMap<&str, String>>) -> bool>)
+             match_filter: &MatchFilter,
+             stream: bool,
+             picture: bool)
              -> Option<Basic> {
     let reader = Reader::new(file.to_str().unwrap());
 
@@ -270,16 +374,23 @@ fn basic<'a>(file: &'a Path,
     };
 
     let mut bodies: HashMap<String, HashMap<&str, String>> = HashMap::new();
+    let mut strings = tag::Strings::read(file);
 
     for unit in reader.unwrap() {
         match unit {
-            Unit::FrameV2(ref fhead, ref fbody) => {
-                bodies.insert(fhead.id(), framebody_to_map(fbody));
+            Unit::FrameV2(ref fhead, fbody) => {
+                let fbody = &strings.fix(fbody);
+                bodies.insert(fhead.id(),
+                              frame_to_map(fbody, &picture_of(fbody, picture)));
             }
             _ => {}
         }
     }
 
+    if stream {
+        bodies.insert("MPEG".to_string(), stream::to_map(file));
+    }
+
     if match_filter(bodies) {
         Some(basic)
     } else {
@@ -287,8 +398,156 @@ fn basic<'a>(file: &'a Path,
     }
 }
 
+fn head_flags(head: &Head) -> Option<Vec<HeadFlag>> {
+    let mut flags = Vec::new();
+
+    if head.has_flag(HeadFlag::Compression) {
+        flags.push(HeadFlag::Compression);
+    }
+
+    if head.has_flag(HeadFlag::Unsynchronisation) {
+        flags.push(HeadFlag::Unsynchronisation);
+    }
+
+    if flags.is_empty() { None } else { Some(flags) }
+}
+
+fn frame_flags(fhead: &FrameHeader) -> Option<Vec<FrameHeaderFlag>> {
+    let mut flags = Vec::new();
+
+    if fhead.has_flag(FrameHeaderFlag::Compression) {
+        flags.push(FrameHeaderFlag::Compression);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::Encryption) {
+        flags.push(FrameHeaderFlag::Encryption);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::FileAlter) {
+        flags.push(FrameHeaderFlag::FileAlter);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::GroupIdentity) {
+        flags.push(FrameHeaderFlag::GroupIdentity);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::ReadOnly) {
+        flags.push(FrameHeaderFlag::ReadOnly);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::TagAlter) {
+        flags.push(FrameHeaderFlag::TagAlter);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::DataLength) {
+        flags.push(FrameHeaderFlag::DataLength);
+    }
+
+    if fhead.has_flag(FrameHeaderFlag::Unsynchronisation) {
+        flags.push(FrameHeaderFlag::Unsynchronisation);
+    }
+
+    if flags.is_empty() { None } else { Some(flags) }
+}
+
+//
+// It is same to `all` but binary properties are kept. it is for re-writing a tag.
+//
+fn load(file: &Path) -> Option<All> {
+    let reader = match Reader::new(file.to_str().unwrap()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            warn!("Can not read: {:?}, {:?}", file, e);
+            return None;
+        }
+    };
+
+    let mut all = All {
+        file: file.to_str().unwrap().to_string(),
+        head: None,
+        frames: None,
+        frame1: None,
+        audio_md5: None,
+    };
+
+    //
+    // rtag can not read UTF-16 of frames other than text frames.
+    //
+    let mut strings = tag::Strings::read(file);
+
+    for unit in reader {
+        match unit {
+            Unit::Header(head) if head.tag_id == "ID3" => {
+                all.head = Some(ViewHead {
+                    version: head.version.to_string(),
+                    flags: head_flags(&head),
+                });
+            }
+            Unit::FrameV2(fhead, fbody) => {
+                all.frames.get_or_insert_with(Vec::new).push(ViewFrame {
+                    flags: frame_flags(&fhead),
+                    body: strings.fix(fbody),
+                    picture: None,
+                    resource: None,
+                    genre: None,
+                });
+            }
+            Unit::FrameV1(frame) => all.frame1 = Some(frame),
+            _ => (),
+        }
+    }
+
+    Some(all)
+}
+
+//
+// It adds a frame of a value, or fills an empty one. a frame that already has a value is kept.
+//
+fn fill(all: &mut All, id: &str, value: &str, version: u8) -> bool {
+    let fbody = match tag::text_frame(id, value, version) {
+        Some(fbody) => fbody,
+        None => return false,
+    };
+
+    let key = match tag::text_of(&fbody) {
+        Some((key, _)) => key,
+        None => return false,
+    };
+
+    let frames = all.frames.get_or_insert_with(Vec::new);
+
+    match frames.iter().position(|vf| tag::text_of(&vf.body).map(|(k, _)| k) == Some(key.clone())) {
+        Some(idx) => {
+            let empty = match tag::text_of(&frames[idx].body) {
+                Some((_, text)) => text.trim().is_empty(),
+                None => true,
+            };
+
+            if !empty {
+                debug!("keep {}: {:?}", id, frames[idx].body);
+                return false;
+            }
+
+            frames[idx].body = fbody;
+        }
+        None => {
+            frames.push(ViewFrame {
+                flags: None,
+                body: fbody,
+                picture: None,
+                resource: None,
+                genre: None,
+            })
+        }
+    }
+
+    true
+}
+
 fn all<'a>(file: &'a Path,
-           match_filter: &Box<Fn(HashMap<String, HashMap<&str, String>>) -> bool>)
+           match_filter: &MatchFilter,
+           dump: &Option<resource::Dump>,
+           stream: bool)
            -> Option<All> {
     fn filter_body(_body: FrameBody) -> FrameBody {
         match _body {
@@ -325,88 +584,68 @@ fn all<'a>(file: &'a Path,
         head: None,
         frames: None,
         frame1: None,
+        audio_md5: None,
     };
 
     let mut bodies: HashMap<String, HashMap<&str, String>> = HashMap::new();
+    let mut strings = tag::Strings::read(file);
 
     for unit in reader.unwrap() {
         match unit {
-            Unit::Header(head) => {
-                let mut flags = Vec::new();
-
-                if head.has_flag(HeadFlag::Compression) {
-                    flags.push(HeadFlag::Compression);
-                }
-
-                if head.has_flag(HeadFlag::Unsynchronisation) {
-                    flags.push(HeadFlag::Unsynchronisation);
-                }
-
+            Unit::Header(head) if head.tag_id == "ID3" => {
                 all.head = Some(ViewHead {
                     version: head.version.to_string(),
-                    flags: if flags.len() > 0 { Some(flags) } else { None },
+                    flags: head_flags(&head),
                 });
             }
             Unit::FrameV2(fhead, fbody) => {
+                let mut fbody = strings.fix(fbody);
 
-                bodies.insert(fhead.id(), framebody_to_map(&fbody));
-
-                if all.frames.is_none() {
-                    all.frames = Some(vec![]);
-                }
-
-                let mut flags = Vec::new();
-
-                if fhead.has_flag(FrameHeaderFlag::Compression) {
-                    flags.push(FrameHeaderFlag::Compression);
-                }
-
-                if fhead.has_flag(FrameHeaderFlag::Encryption) {
-                    flags.push(FrameHeaderFlag::Encryption);
-                }
-
-                if fhead.has_flag(FrameHeaderFlag::FileAlter) {
-                    flags.push(FrameHeaderFlag::FileAlter);
-                }
-
-                if fhead.has_flag(FrameHeaderFlag::GroupIdentity) {
-                    flags.push(FrameHeaderFlag::GroupIdentity);
-                }
-
-                if fhead.has_flag(FrameHeaderFlag::ReadOnly) {
-                    flags.push(FrameHeaderFlag::ReadOnly);
-                }
+                let picture = artwork::Picture::from_framebody(&fbody);
+                let resource = match *dump {
+                    Some(ref dump) => resource::dump(&mut fbody, dump),
+                    None => None,
+                };
 
-                if fhead.has_flag(FrameHeaderFlag::TagAlter) {
-                    flags.push(FrameHeaderFlag::TagAlter);
-                }
+                bodies.insert(fhead.id(), frame_to_map(&fbody, &picture));
 
-                if fhead.has_flag(FrameHeaderFlag::DataLength) {
-                    flags.push(FrameHeaderFlag::DataLength);
-                }
+                let genre = genre::tcon(&fbody);
 
-                if fhead.has_flag(FrameHeaderFlag::Unsynchronisation) {
-                    flags.push(FrameHeaderFlag::Unsynchronisation);
+                if all.frames.is_none() {
+                    all.frames = Some(vec![]);
                 }
 
                 match all.frames {
                     Some(ref mut frames) => {
                         frames.push(ViewFrame {
-                            flags: if flags.len() > 0 { Some(flags) } else { None },
+                            flags: frame_flags(&fhead),
                             body: filter_body(fbody),
+                            picture,
+                            resource,
+                            genre,
                         });
                     }
                     _ => {}
                 };
 
             }
-            Unit::FrameV1(frame) => {
+            Unit::FrameV1(mut frame) => {
+                //
+                // a genre is shown as a name, and `update` writes it back as an index.
+                //
+                if let Some(name) = genre::from_frame1(&frame.genre) {
+                    frame.genre = name.to_string();
+                }
                 all.frame1 = Some(frame);
             }
             _ => (),
         }
     }
 
+    if stream {
+        bodies.insert("MPEG".to_string(), stream::to_map(file));
+    }
+
     if match_filter(bodies) {
         Some(all)
     } else {
@@ -414,13 +653,22 @@ fn all<'a>(file: &'a Path,
     }
 }
 
-fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bool> {
+//
+// A filter of `--match`. it takes properties by a frame id. see `match_expr`
+//
+type MatchFilter = Box<dyn Fn(HashMap<String, HashMap<&str, String>>) -> bool>;
+
+//
+// Tokens of an expression of `--match`. ex) TIT2.text~'Dio' => ["TIT2", ".", "text", "~", "Dio"]
+//
+fn match_tokens(exp: &str) -> Vec<String> {
     use std::str::Chars;
 
     fn take_token(chars: &mut Chars) -> (char, String) {
         let mut tk = ' ';
         let value = chars.take_while(|ch| match ch {
-                &'!' | &'^' | &'=' | &'~' | &'$' | &'.' | &'(' | &')' | &'&' | &'|' | &' ' => {
+                &'!' | &'^' | &'=' | &'~' | &'$' | &'<' | &'>' | &'.' | &'(' | &')' | &'&' |
+                &'|' | &' ' => {
                     tk = *ch;
                     false
                 }
@@ -444,7 +692,7 @@ fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bo
                 let v = take_value(ch, &mut chars);
                 tokens.push(v);
             }
-            '!' | '^' | '=' | '~' | '$' | '.' | '(' | ')' | '&' | '|' => {
+            '!' | '^' | '=' | '~' | '$' | '<' | '>' | '.' | '(' | ')' | '&' | '|' => {
                 tokens.push(ch.to_string());
             }
             ' ' => {
@@ -462,6 +710,24 @@ fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bo
         }
     }
 
+    tokens
+}
+
+//
+// Ids of properties in an expression of `--match`. ex) MPEG.health='bad' => ["MPEG"]
+//
+fn match_ids(exp: &str) -> Vec<String> {
+    let tokens = match_tokens(exp);
+
+    tokens.windows(2)
+        .filter(|pair| pair[1] == ".")
+        .map(|pair| pair[0].clone())
+        .collect()
+}
+
+fn match_expr(exp: &str) -> MatchFilter {
+    let tokens = match_tokens(exp);
+
     let mut iter = tokens.iter();
     let mut stack = Vec::new();
     let mut ordered = Vec::new();
@@ -477,7 +743,7 @@ fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bo
 
     while let Some(token) = iter.next() {
         match token.as_str() {
-            "!" | "^" | "=" | "~" | "$" | "." | "(" | "&" | "|" => {
+            "!" | "^" | "=" | "~" | "$" | "<" | ">" | "." | "(" | "&" | "|" => {
                 stack.push(Tk::Del(token.clone()))
             }
             ")" => {
@@ -507,7 +773,9 @@ fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bo
                     Some(Tk::Del(ref tk)) if tk.as_str() == "!" || tk.as_str() == "^" ||
                                              tk.as_str() == "=" ||
                                              tk.as_str() == "~" ||
-                                             tk.as_str() == "$" => {
+                                             tk.as_str() == "$" ||
+                                             tk.as_str() == "<" ||
+                                             tk.as_str() == ">" => {
 
                         let is_prop = if let Some(&Tk::Prop(_, _)) = stack.last() {
                             true
@@ -594,7 +862,7 @@ fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bo
 
                     if op == "" || id == "" {
                         panic!("\n\n---------------------\n\
-                                \"<id>.<property> (=!~$) <value>\" \
+                                \"<id>.<property> (=!~^$<>) <value>\" \
                                 ex) TIT1.text~\"Dio Live\"\n---------------------\n\n");
                     }
 
@@ -608,6 +876,13 @@ fn match_expr(exp: &str) -> Box<Fn(HashMap<String, HashMap<&str, String>>) -> bo
                                         "~" => v.contains(value.as_str()),
                                         "^" => v.starts_with(value.as_str()),
                                         "$" => v.ends_with(value.as_str()),
+                                        "<" | ">" => {
+                                            match (v.parse::<f64>(), value.parse::<f64>()) {
+                                                (Ok(v), Ok(value)) if op == "<" => v < value,
+                                                (Ok(v), Ok(value)) => v > value,
+                                                _ => false,
+                                            }
+                                        }
                                         _ => false,
                                     }
                                 }
@@ -645,12 +920,29 @@ fn read(matches: clap::ArgMatches) {
 
     let format = matches.value_of("format");
 
-    let match_exec: Box<Fn(HashMap<String, HashMap<&str, String>>) -> bool> =
+    let match_exec: MatchFilter =
         match matches.value_of("match") {
             Some(expr) => match_expr(expr),
             _ => Box::new(|_| true),
         };
 
+    let dump = match matches.value_of("binary") {
+        Some("base64") => Some(resource::Dump::Base64),
+        Some("file") => {
+            Some(resource::Dump::Dir(PathBuf::from(matches.value_of("binary-dir").unwrap_or("."))))
+        }
+        _ => None,
+    };
+
+    let audio_md5 = matches.is_present("audio-md5");
+
+    //
+    // MPEG frames and pictures are read only if they are matched. see `stream::to_map`, `picture_of`
+    //
+    let ids = matches.value_of("match").map(match_ids).unwrap_or_default();
+    let stream = ids.iter().any(|id| id == "MPEG");
+    let picture = ids.iter().any(|id| id == "APIC" || id == "PIC");
+
     let start = PreciseTime::now();
 
     for file in files {
@@ -661,56 +953,48 @@ fn read(matches: clap::ArgMatches) {
 
                 match format {
                     Some("t") => {
-                        match simple(path.as_path(), &match_exec) {
-                            Some(s) => println!("{}", s),
-                            _ => {}
-                        };
+                        if let Some(s) = simple(path.as_path(), &match_exec, stream, picture) {
+                            println!("{}", s);
+                        }
                     }
                     Some("tt") => {
-                        match all(path.as_path(), &match_exec) {
-                            Some(a) => println!("{}", a),
-                            _ => {}
-                        };
+                        if let Some(mut a) = all(path.as_path(), &match_exec, &dump, stream) {
+                            if audio_md5 {
+                                a.audio_md5 = checksum::compute(&path);
+                            }
+                            println!("{}", a)
+                        }
                     }
                     Some("j") => {
-                        match simple(path.as_path(), &match_exec) {
-                            Some(a) => {
-                                let json_str = match serde_json::to_string_pretty(&a) {
-                                    Ok(s) => s,
-                                    _ => "{\"err\": \"\"}".to_string(),
-                                };
-                                println!("{},", json_str);
-                            }
-                            _ => {}
-                        };
+                        if let Some(a) = simple(path.as_path(), &match_exec, stream, picture) {
+                            let json_str = match serde_json::to_string_pretty(&a) {
+                                Ok(s) => s,
+                                _ => "{\"err\": \"\"}".to_string(),
+                            };
+                            println!("{},", json_str);
+                        }
                     }
                     Some("jj") => {
-                        match all(path.as_path(), &match_exec) {
-                            Some(a) => {
-                                let json_str = match serde_json::to_string_pretty(&a) {
-                                    Ok(s) => s,
-                                    _ => "{\"err\": \"\"}".to_string(),
-                                };
-                                println!("//<");
-                                println!("{}", json_str);
-                                println!("//>");
+                        if let Some(mut a) = all(path.as_path(), &match_exec, &dump, stream) {
+                            if audio_md5 {
+                                a.audio_md5 = checksum::compute(&path);
                             }
-                            _ => {}
-                        };
+                            let json_str = match serde_json::to_string_pretty(&a) {
+                                Ok(s) => s,
+                                _ => "{\"err\": \"\"}".to_string(),
+                            };
+                            println!("//<");
+                            println!("{}", json_str);
+                            println!("//>");
+                        }
                     }
-                    Some("f") => {
-                        match simple(path.as_path(), &match_exec) {
-                            Some(_) => println!("{}", file),
-                            _ => {}
-                        };
+                    Some("f") if simple(path.as_path(), &match_exec, stream, picture).is_some() => {
+                        println!("{}", file);
                     }
                     Some("ff") => {
-                        match basic(path.as_path(), &match_exec) {
-                            Some(b) => {
-                                print!("{}", b);
-                                println!("---");
-                            }
-                            _ => {}
+                        if let Some(b) = basic(path.as_path(), &match_exec, stream, picture) {
+                            print!("{}", b);
+                            println!("---");
                         }
                     }
                     _ => {}
@@ -725,323 +1009,340 @@ fn read(matches: clap::ArgMatches) {
     println!("#{}", start.to(PreciseTime::now()));
 }
 
-fn write(matches: clap::ArgMatches) {
-    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+fn clean_write(all: &All, shrink: &artwork::Shrink, base: &Path) -> ::std::io::Result<()> {
+    let file = all.file.as_str();
 
-    use std::fs::File;
-    use std::io::{self, BufReader, BufRead, Read};
-    use std::result;
+    debug!("clean write: {}", file);
 
-    use hyper::Url;
-    use hyper::Client;
+    let writer = Writer::new(file)?;
+    let frames: Vec<Unit> = match all.frames {
+        Some(ref frames) => {
+            let iter = frames.iter();
 
-    use rtag::rw::Readable;
+            iter.map(|vf| {
+                    let mut frame_body = vf.body.clone();
 
-    #[derive(Debug, Hash, PartialEq, Eq)]
-    enum WriteOption {
-        Clean,
-        None,
-    }
+                    resource::embed(&mut frame_body, &vf.resource, shrink, base);
 
-    fn write(json_string: &String, options: &Option<HashSet<WriteOption>>) {
-        let all: result::Result<All, serde_json::Error> =
-            serde_json::from_str(json_string.as_str());
+                    //
+                    // there is no PIC in version 4.
+                    //
+                    let frame_body = artwork::pic_to_apic(frame_body);
 
-        let mut all = match all {
-            Ok(all) => all,
-            Err(_) => panic!("\n\n=================>Invalid json: \n{}", json_string),
-        };
+                    let id = framebody_to_id(&frame_body, 4);
 
-        let result = match options {
-            &Some(ref options) => {
-                if options.contains(&WriteOption::Clean) {
-                    clean_write(&all)
-                } else {
-                    update(&mut all)
-                }
-            }
-            _ => update(&mut all),
-        };
+                    Unit::FrameV2(FrameHeader::V24(FrameHeaderV4 {
+                                      id: id.to_string(),
+                                      size: 0,
+                                      status_flag: 0,
+                                      encoding_flag: 0,
+                                  }),
+                                  frame_body)
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
 
-        match result {
-            Ok(_) => debug!("Write done {}", all.file),
-            Err(e) => warn!("Write err {:?}", e),
-        };
+    //
+    // a clean write drops old tags and ID3v1. see `Writer::write`
+    //
+    let (_, head_size, bytes) = to_bytes(&writer, writer.fix_units(&frames)?)?;
+    let original = std::fs::read(file)?;
+    let layout = audio::layout(&original);
+
+    let end = match layout.trailing.first() {
+        Some(&("ID3v1", start, _)) => start,
+        _ => original.len(),
+    };
+
+    let mut out = bytes[..10 + head_size as usize].to_vec();
+    out.extend_from_slice(&original[layout.id3v2_len..end]);
+
+    untag::replace(Path::new(file), &out)
+}
+
+//
+// Frames must be in a version of a head. it is what `Writer::write` checks.
+//
+fn check_version(units: &[Unit]) -> ::std::io::Result<()> {
+    let version = units.iter()
+        .filter_map(|unit| match *unit {
+            Unit::Header(ref head) => Some(head.version),
+            _ => None,
+        })
+        .next()
+        .unwrap_or(4);
+
+    let same = units.iter().all(|unit| match *unit {
+        Unit::FrameV2(FrameHeader::V22(_), _) => version == 2,
+        Unit::FrameV2(FrameHeader::V23(_), _) => version == 3,
+        Unit::FrameV2(FrameHeader::V24(_), _) => version == 4,
+        _ => true,
+    });
+
+    if same {
+        Ok(())
+    } else {
+        Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData,
+                                  "exist different version of 'Unit'"))
     }
+}
 
-    fn clean_write(all: &All) -> io::Result<()> {
-        let file = all.file.as_str();
+//
+// Bytes of units by `Writer`, and (has frame1, size of a tag) with them. see `Writer::to_bytes`
+// frames of strings are encoded by `tag::encode_body`, because rtag writes them in UTF-8
+// whatever the encoding is.
+//
+fn to_bytes(writer: &Writer, units: Vec<Unit>) -> ::std::io::Result<(bool, u32, Vec<u8>)> {
+    use rtag::rw::Readable;
+    use std::io::Cursor;
 
-        debug!("clean write: {}", file);
+    let mut head = None;
+    let mut frame1 = None;
+    let mut frame_bytes = Vec::new();
 
-        let writer = Writer::new(file)?;
-        let frames: Vec<Unit> = match all.frames {
-            Some(ref frames) => {
-                let iter = frames.iter();
+    let version = units.iter()
+        .filter_map(|unit| match *unit {
+            Unit::Header(ref head) => Some(head.version),
+            _ => None,
+        })
+        .next()
+        .unwrap_or(4);
 
-                iter.map(|vf| {
-                        let mut frame_body = vf.body.clone();
+    for unit in units {
+        match unit {
+            Unit::Header(h) => head = Some(h),
+            Unit::FrameV1(frame) => frame1 = Some(frame),
+            Unit::FrameV2(mut fhead, fbody) => {
+                let id = framebody_to_id(&fbody, version);
+                //
+                // compressed or encrypted frames are written by rtag.
+                //
+                let body = match fhead {
+                    FrameHeader::V23(ref h) if h.encoding_flag != 0 => None,
+                    FrameHeader::V24(ref h) if h.encoding_flag != 0 => None,
+                    _ => tag::encode_body(&fbody),
+                };
 
-                        if let FrameBody::APIC(ref mut frame) = frame_body {
-                            match extract_url(&frame.description) {
-                                Some((replaced, extraced)) => {
-                                    match resource_to_bytes(extraced.as_str()) {
-                                        Some(bytes) => {
-                                            frame.description = replaced;
-                                            frame.picture_data = bytes;
-                                        }
-                                        _ => {}
-                                    };
-                                }
-                                _ => {}
-                            }
-                        }
+                let body = match body {
+                    Some(body) => body,
+                    None => {
+                        frame_bytes.extend(writer.frame((fhead, fbody))?);
+                        continue;
+                    }
+                };
 
-                        let id = framebody_to_id(&frame_body, 4);
-
-                        Unit::FrameV2(FrameHeader::V24(FrameHeaderV4 {
-                                          id: id.to_string(),
-                                          size: 0,
-                                          status_flag: 0,
-                                          encoding_flag: 0,
-                                      }),
-                                      frame_body)
-                    })
-                    .collect()
+                let mut cursor = Cursor::new(Vec::new());
+                match fhead {
+                    FrameHeader::V22(ref mut h) => {
+                        h.id = id.to_string();
+                        h.size = body.len() as u32;
+                        h.write(&mut cursor, 2)?;
+                    }
+                    FrameHeader::V23(ref mut h) => {
+                        h.id = id.to_string();
+                        h.size = body.len() as u32;
+                        h.write(&mut cursor, 3)?;
+                    }
+                    FrameHeader::V24(ref mut h) => {
+                        h.id = id.to_string();
+                        h.size = body.len() as u32;
+                        h.write(&mut cursor, 4)?;
+                    }
+                }
+                frame_bytes.extend(cursor.into_inner());
+                frame_bytes.extend(body);
             }
-            _ => Vec::new(),
-        };
+            _ => (),
+        }
+    }
+
+    let mut head = head.unwrap_or(Head {
+        tag_id: "ID3".to_string(),
+        version: 4,
+        minor_version: 0,
+        flag: 0,
+        size: 0,
+    });
 
-        writer.write(frames, true)
+    if head.has_flag(HeadFlag::Unsynchronisation) {
+        let len = frame_bytes.len();
+        frame_bytes = Cursor::new(frame_bytes).to_unsynchronize(len)?;
     }
 
-    fn update(all: &mut All) -> io::Result<()> {
-        let file = all.file.as_str();
+    head.size = frame_bytes.len() as u32;
+    let size = head.size;
 
-        debug!("update write: {}", file);
+    let mut bytes = writer.head(head)?;
+    bytes.extend(frame_bytes);
 
-        let writer = Writer::new(file)?;
+    let has_frame1 = match frame1 {
+        Some(frame1) => {
+            bytes.extend(writer.frame1(frame1)?);
+            true
+        }
+        None => false,
+    };
 
-        let (version, head_unit) = if let Some(ref vhead) = all.head {
-            let version: u8 = match vhead.version.parse() {
-                Ok(v) => v,
-                Err(_) => 4,
-            };
+    Ok((has_frame1, size, bytes))
+}
 
-            let mut head = Head {
-                tag_id: "ID3".to_string(),
-                version: version,
-                minor_version: 0,
-                flag: 0,
-                size: 0,
-            };
+fn update(all: &mut All, shrink: &artwork::Shrink, base: &Path) -> ::std::io::Result<()> {
+    let file = all.file.as_str();
 
-            match vhead.flags {
-                Some(ref flags) => {
-                    for flag in flags.clone() {
-                        head.set_flag(flag);
-                    }
-                }
-                _ => {}
-            };
+    debug!("update write: {}", file);
 
-            (version, Unit::Header(head))
-        } else {
-            (4,
-             Unit::Header(Head {
-                 tag_id: "ID3".to_string(),
-                 version: 4,
-                 minor_version: 0,
-                 flag: 0,
-                 size: 0,
-             }))
+    let writer = Writer::new(file)?;
+
+    let (version, head_unit) = if let Some(ref vhead) = all.head {
+        let version: u8 = vhead.version.parse().unwrap_or(4);
+
+        let mut head = Head {
+            tag_id: "ID3".to_string(),
+            version,
+            minor_version: 0,
+            flag: 0,
+            size: 0,
         };
 
-        let mut frames: Vec<Unit> = match all.frames {
-            Some(ref frames) => {
-                let iter = frames.iter();
+        if let Some(ref flags) = vhead.flags {
+            for flag in flags.clone() {
+                head.set_flag(flag);
+            }
+        }
 
-                iter.map(|vf| {
-                        let mut frame_body = vf.body.clone();
+        (version, Unit::Header(head))
+    } else {
+        (4,
+         Unit::Header(Head {
+             tag_id: "ID3".to_string(),
+             version: 4,
+             minor_version: 0,
+             flag: 0,
+             size: 0,
+         }))
+    };
 
-                        if let FrameBody::PIC(ref mut frame) = frame_body {
-                            match extract_url(&frame.description) {
-                                Some((replaced, extraced)) => {
-                                    match resource_to_bytes(extraced.as_str()) {
-                                        Some(bytes) => {
-                                            frame.description = replaced;
-                                            frame.picture_data = bytes;
-                                        }
-                                        _ => {}
-                                    };
-                                }
-                                _ => {}
-                            }
-                        }
+    let mut frames: Vec<Unit> = match all.frames {
+        Some(ref frames) => {
+            let iter = frames.iter();
 
-                        if let FrameBody::APIC(ref mut frame) = frame_body {
-                            match extract_url(&frame.description) {
-                                Some((replaced, extraced)) => {
-                                    match resource_to_bytes(extraced.as_str()) {
-                                        Some(bytes) => {
-                                            frame.description = replaced;
-                                            frame.picture_data = bytes;
-                                        }
-                                        _ => {}
-                                    };
-                                }
-                                _ => {}
-                            }
-                        }
+            iter.map(|vf| {
+                    let mut frame_body = vf.body.clone();
 
-                        let id = framebody_to_id(&frame_body, version);
+                    resource::embed(&mut frame_body, &vf.resource, shrink, base);
 
-                        let frame_head = match version {
-                            2 => {
-                                FrameHeader::V22(FrameHeaderV2 {
-                                    id: id.to_string(),
-                                    size: 0,
-                                })
-                            }
-                            3 => {
-                                let mut header = FrameHeader::V23(FrameHeaderV3 {
-                                    id: id.to_string(),
-                                    size: 0,
-                                    status_flag: 0,
-                                    encoding_flag: 0,
-                                });
-
-                                match vf.flags {
-                                    Some(ref flags) => {
-                                        for flag in flags.clone() {
-                                            header.set_flag(flag);
-                                        }
-                                    }
-                                    _ => {}
-                                };
+                    let id = framebody_to_id(&frame_body, version);
 
-                                header
+                    let frame_head = match version {
+                        2 => {
+                            FrameHeader::V22(FrameHeaderV2 {
+                                id: id.to_string(),
+                                size: 0,
+                            })
+                        }
+                        3 => {
+                            let mut header = FrameHeader::V23(FrameHeaderV3 {
+                                id: id.to_string(),
+                                size: 0,
+                                status_flag: 0,
+                                encoding_flag: 0,
+                            });
+
+                            if let Some(ref flags) = vf.flags {
+                                for flag in flags.clone() {
+                                    header.set_flag(flag);
+                                }
                             }
-                            _ => {
-                                let mut header = FrameHeader::V24(FrameHeaderV4 {
-                                    id: id.to_string(),
-                                    size: 0,
-                                    status_flag: 0,
-                                    encoding_flag: 0,
-                                });
-
-                                match vf.flags {
-                                    Some(ref flags) => {
-                                        for flag in flags.clone() {
-                                            header.set_flag(flag);
-                                        }
-                                    }
-                                    _ => {}
-                                };
 
-                                header
+                            header
+                        }
+                        _ => {
+                            let mut header = FrameHeader::V24(FrameHeaderV4 {
+                                id: id.to_string(),
+                                size: 0,
+                                status_flag: 0,
+                                encoding_flag: 0,
+                            });
+
+                            if let Some(ref flags) = vf.flags {
+                                for flag in flags.clone() {
+                                    header.set_flag(flag);
+                                }
                             }
-                        };
 
-                        Unit::FrameV2(frame_head, frame_body)
-                    })
-                    .collect()
-            }
-            _ => Vec::new(),
-        };
+                            header
+                        }
+                    };
 
-        if let Some(ref frame1) = all.frame1 {
-            frames.push(Unit::FrameV1(frame1.clone()));
+                    Unit::FrameV2(frame_head, frame_body)
+                })
+                .collect()
         }
+        _ => Vec::new(),
+    };
 
-        frames.insert(0, head_unit);
-
-        writer.write(frames, false)
+    if let Some(ref frame1) = all.frame1 {
+        let mut frame1 = frame1.clone();
+        if frame1.genre.trim().parse::<u8>().is_err() {
+            frame1.genre = genre::index(&frame1.genre).unwrap_or(genre::NONE).to_string();
+        }
+        frames.push(Unit::FrameV1(frame1));
     }
 
-    fn extract_url(value: &String) -> Option<(String, String)> {
-        let re = Regex::new(r"#\{(.*)\}").unwrap();
+    frames.insert(0, head_unit);
+    check_version(&frames)?;
 
-        re.captures(value.as_str()).and_then(|c| {
-            let cap = c.get(1).map_or("", |m| m.as_str());
-            Some((re.replace(value, "").into_owned(), cap.to_string()))
-        })
-    }
+    //
+    // a tag is written in space of the old one if it fits. see `padding::write_tag`
+    //
+    let (has_frame1, head_size, bytes) = to_bytes(&writer, frames)?;
+    let (tag, frame1) = bytes.split_at(10 + head_size as usize);
 
-    fn resource_to_bytes(cap_url: &str) -> Option<Vec<u8>> {
-
-        fn file(url: Url) -> Option<Vec<u8>> {
-            let path = match url.to_file_path() {
-                Ok(path) => path,
-                Err(e) => {
-                    error!("Invalid file path: {:?}", e);
-                    return None;
-                }
-            };
+    padding::write_tag(Path::new(file), tag, if has_frame1 { Some(frame1) } else { None })
+}
 
-            debug!("file: {:?}", path);
+fn write(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
 
-            let mut fs = match File::open(path) {
-                Ok(fs) => fs,
-                Err(e) => {
-                    error!("Can not read a file: {:?}", e);
-                    return None;
-                }
-            };
+    use std::fs::File;
+    use std::io::{BufReader, BufRead};
+    use std::result;
 
-            match fs.all_bytes() {
-                Ok(bytes) => Some(bytes),
-                Err(e) => {
-                    error!("Unknown error: {:?}", e);
-                    None
-                }
-            }
-        }
+    #[derive(Debug, Hash, PartialEq, Eq)]
+    enum WriteOption {
+        Clean,
+        None,
+    }
 
-        fn http(url: Url) -> Option<Vec<u8>> {
-            debug!("http: '{}'", url);
+    fn write(json_string: &String,
+             options: &Option<HashSet<WriteOption>>,
+             shrink: &artwork::Shrink,
+             base: &Path) {
+        let all: result::Result<All, serde_json::Error> =
+            serde_json::from_str(json_string.as_str());
 
-            let client = Client::new();
-            let mut response = match client.get(url).send() {
-                Ok(response) => response,
-                Err(e) => {
-                    error!("Can not send http request: {:?}", e);
-                    return None;
-                }
-            };
+        let mut all = match all {
+            Ok(all) => all,
+            Err(_) => panic!("\n\n=================>Invalid json: \n{}", json_string),
+        };
 
-            let mut buf = vec![0u8; 1024];
-            let mut dst = Vec::new();
-            while let Ok(read) = response.read(&mut buf) {
-                if read == 0 {
-                    break;
-                }
-                if read < buf.len() {
-                    dst.extend_from_slice(&buf[..read]);
+        let result = match options {
+            &Some(ref options) => {
+                if options.contains(&WriteOption::Clean) {
+                    clean_write(&all, shrink, base)
                 } else {
-                    dst.extend_from_slice(&buf);
+                    update(&mut all, shrink, base)
                 }
             }
-
-            Some(dst)
-        }
-
-        debug!("resource: {}", cap_url);
-
-        let parsed_url = match Url::parse(cap_url) {
-            Ok(parsed_url) => parsed_url,
-            Err(_) => {
-                error!("Invalid url: {}", cap_url);
-                return None;
-            }
+            _ => update(&mut all, shrink, base),
         };
 
-        if parsed_url.scheme() == "http" || parsed_url.scheme() == "https" {
-            http(parsed_url)
-        } else {
-            file(parsed_url)
-        }
-
+        match result {
+            Ok(_) => debug!("Write done {}", all.file),
+            Err(e) => warn!("Write err {:?}", e),
+        };
     }
 
     fn read_option<'a>(line: String) -> Option<HashSet<WriteOption>> {
@@ -1060,6 +1361,11 @@ fn write(matches: clap::ArgMatches) {
         Some(options)
     }
 
+    let shrink = artwork::Shrink {
+        max_dimension: matches.value_of("artwork-max-dimension").and_then(|v| v.parse().ok()),
+        max_bytes: matches.value_of("artwork-max-bytes").and_then(|v| v.parse().ok()),
+    };
+
     for file in files {
         trace!("{}", file);
 
@@ -1068,6 +1374,8 @@ fn write(matches: clap::ArgMatches) {
             Err(_) => panic!("Can not open json file. {}", file),
         };
 
+        let base = Path::new(file).parent().unwrap_or(Path::new(""));
+
         let mut item = String::new();
         let mut options: Option<HashSet<WriteOption>> = None;
 
@@ -1079,7 +1387,7 @@ fn write(matches: clap::ArgMatches) {
                 item.clear();
                 options = read_option(line);
             } else if line.starts_with("//>") {
-                write(&item, &options);
+                write(&item, &options, &shrink, base);
             } else {
                 item.push_str(line.as_str());
                 item.push_str("\n");
@@ -1105,6 +1413,9 @@ fn transform(matches: clap::ArgMatches) {
 
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::APIC(APIC {
                     text_encoding: TextEncoding::UTF8,
                     mime_type: "image/jpeg".to_string(),
@@ -1118,6 +1429,9 @@ fn transform(matches: clap::ArgMatches) {
         if !basic.title.is_empty() {
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::TIT2(TEXT {
                     text_encoding: TextEncoding::UTF8,
                     text: basic.title.clone(),
@@ -1128,6 +1442,9 @@ fn transform(matches: clap::ArgMatches) {
         if !basic.artist.is_empty() {
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::TPE1(TEXT {
                     text_encoding: TextEncoding::UTF8,
                     text: basic.artist.clone(),
@@ -1138,6 +1455,9 @@ fn transform(matches: clap::ArgMatches) {
         if !basic.band.is_empty() {
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::TPE2(TEXT {
                     text_encoding: TextEncoding::UTF8,
                     text: basic.band.clone(),
@@ -1148,6 +1468,9 @@ fn transform(matches: clap::ArgMatches) {
         if !basic.album.is_empty() {
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::TALB(TEXT {
                     text_encoding: TextEncoding::UTF8,
                     text: basic.album.clone(),
@@ -1158,6 +1481,9 @@ fn transform(matches: clap::ArgMatches) {
         if !basic.year.is_empty() {
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::TDRC(TEXT {
                     text_encoding: TextEncoding::UTF8,
                     text: basic.year.clone(),
@@ -1168,6 +1494,9 @@ fn transform(matches: clap::ArgMatches) {
         if !basic.track.is_empty() {
             view_frames.push(ViewFrame {
                 flags: None,
+                picture: None,
+                resource: None,
+                genre: None,
                 body: FrameBody::TRCK(TEXT {
                     text_encoding: TextEncoding::UTF8,
                     text: basic.track.clone(),
@@ -1180,6 +1509,7 @@ fn transform(matches: clap::ArgMatches) {
             head: None,
             frames: Some(view_frames),
             frame1: None,
+            audio_md5: None,
         };
 
         let json_str = match serde_json::to_string_pretty(&all) {
@@ -1287,6 +1617,146 @@ fn main() {
                           
                           \
                           -t --transform 'ff format convert to jj format'
+
+                          \
+                          --artwork-max-dimension=[PIXELS] 'with -w, downscale a embedding \
+                          image to fit in PIXELS x PIXELS'
+
+                          \
+                          --artwork-max-bytes=[BYTES] 'with -w, re-encode a embedding image \
+                          as jpeg until it is smaller than BYTES'
+
+                          \
+                          --binary=[BINARY] 'with -f tt|jj, take binary properties out to a \
+                          resource. (file|base64) file=save to a file, base64=inline data uri'
+
+                          \
+                          --binary-dir=[DIR] 'directory to save binary files. default is \
+                          current directory'
+
+                          \
+                          -o --organize=[TEMPLATE] 'move files to a path from tag values. ex) \
+                          -o \"{TPE2|TPE1}/{TDRC:year} - {TALB}/{TRCK:02} {TIT2}.mp3\"'
+
+                          \
+                          --target-dir=[DIR] 'with -o, base directory of moved files. default \
+                          is current directory'
+
+                          \
+                          --copy 'with -o, copy instead of move'
+
+                          \
+                          -n --dry-run 'print what will be done without changing files'
+
+                          \
+                          -p --from-path=[PATTERN] 'fill tags from a file path. ex) -p \
+                          \"{TPE1}/{TDRC} - {TALB}/{TRCK} - {TIT2}\"'
+
+                          \
+                          --copy-tags=[SOURCE] 'copy tags of SOURCE to INPUT files. if SOURCE \
+                          is a directory, files of it are paired with files of INPUT directories'
+
+                          \
+                          --pair-by=[PAIR] 'with --copy-tags, how files are paired. default is \
+                          track. (track|name)'
+
+                          \
+                          --include=[IDS] 'with --copy-tags, frame ids to copy. ex) APIC,TALB'
+
+                          \
+                          --exclude=[IDS] 'with --copy-tags, frame ids not to copy. ex) \
+                          PRIV,TXXX:CATALOGNUMBER'
+
+                          \
+                          --sync=[TO] 'make ID3v1 from ID3v2 frames, or fill missing ID3v2 \
+                          frames from ID3v1. (v1|v2)'
+
+                          \
+                          --convert=[VERSION] 'convert ID3v2 to a version. frames are mapped \
+                          to the version. (2|3|4)'
+
+                          \
+                          --encoding=[ENCODING] 're-encode text of all frames. COMR, OWNE and \
+                          SYLT can not be UTF-16, and are kept. utf16 is UTF-16LE with a BOM. \
+                          (iso88591|utf16|utf16be|utf8)'
+
+                          \
+                          --fix-mojibake=[CODEPAGE] 're-decode ISO-8859-1 text in a legacy \
+                          code page. (auto|cp949|euc-kr|shift-jis|gbk|big5)'
+
+                          \
+                          --normalize-genre 'rewrite TCON to names of the genre list'
+
+                          \
+                          --genre-alias=[FILE] 'with --normalize-genre, a json object of \
+                          aliases. ex) {\"hiphop\": \"Hip-Hop\"}'
+
+                          \
+                          --normalize-number=[FORMAT] 'rewrite TRCK and TPOS in a format. ex) \
+                          --normalize-number=\"{n:02}/{t:02}\"'
+
+                          \
+                          --fill-total 'with --normalize-number, fill a missing total by \
+                          counting files in the album directory'
+
+                          \
+                          --clean=[RULES] 'clean text of frames with rules. a comma list of \
+                          (trim|collapse|title-case|feat) or a json file of rules'
+
+                          \
+                          --clean-frames=[IDS] 'with --clean, frames to clean. default is \
+                          TIT2,TPE1,TPE2,TALB'
+
+                          \
+                          --duplicates=[BY] 'print groups of the same song. (tag|hash) \
+                          tag=artist, title and duration, hash=md5 of audio without tags'
+
+                          \
+                          --audio-md5 'with -f tt|jj, show md5 of audio without tags'
+
+                          \
+                          --store-checksum 'save md5 of audio without tags in TXXX:AUDIO_MD5'
+
+                          \
+                          --verify-checksum 'print files whose audio does not match \
+                          TXXX:AUDIO_MD5'
+
+                          \
+                          --check-stream 'walk all MPEG frames and print the health of files'
+
+                          \
+                          --replaygain=[ALBUM] 'save ReplayGain 2.0 in TXXX:REPLAYGAIN_*. \
+                          (track|dir|album) dir=a directory is an album, album=by TALB'
+
+                          \
+                          --check-album=[BY] 'print fields that differ in an album, and missing \
+                          or duplicate tracks. (dir|tag) tag=TALB and TPE2'
+
+                          \
+                          --fix-album 'with --check-album, set a value of the most files'
+
+                          \
+                          --lint=[RULES] 'print frames that break rules, and exit with 1. \
+                          default or a json file of rules. with -f j|jj, in json'
+
+                          \
+                          --strip=[PATTERNS] 'remove frames of a comma list of patterns. \
+                          ex) PRIV,W*,TXXX:Encoded by,COMM:eng:iTun* with -m, only matched files'
+
+                          \
+                          --remove-tags=[TAGS] 'remove tags and keep the audio. a comma list of \
+                          (id3v2|id3v1|apev2|lyrics3|all)'
+
+                          \
+                          --padding=[BYTES] 'set padding of ID3v2 to bytes, or print it with \
+                          report. a tag that fits in the old one is written in place'
+
+                          \
+                          --compact 'remove padding of ID3v2'
+
+                          \
+                          --stats 'print counts of versions, encodings, frames and bytes of tags. \
+                          with -f j|jj, in json'
             ")
         .get_matches();
 
@@ -1294,7 +1764,267 @@ fn main() {
         write(matches);
     } else if matches.is_present("transform") {
         transform(matches);
+    } else if matches.is_present("organize") {
+        organize::organize(matches);
+    } else if matches.is_present("from-path") {
+        frompath::from_path(matches);
+    } else if matches.is_present("copy-tags") {
+        copy::copy_tags(matches);
+    } else if matches.is_present("sync") {
+        sync::sync(matches);
+    } else if matches.is_present("convert") {
+        convert::convert(matches);
+    } else if matches.is_present("encoding") {
+        reencode::reencode(matches);
+    } else if matches.is_present("fix-mojibake") {
+        mojibake::repair(matches);
+    } else if matches.is_present("normalize-genre") {
+        genre::normalize(matches);
+    } else if matches.is_present("normalize-number") {
+        number::normalize(matches);
+    } else if matches.is_present("clean") {
+        clean::clean(matches);
+    } else if matches.is_present("duplicates") {
+        duplicate::duplicates(matches);
+    } else if matches.is_present("store-checksum") {
+        checksum::store(matches);
+    } else if matches.is_present("verify-checksum") {
+        checksum::verify(matches);
+    } else if matches.is_present("check-stream") {
+        stream::check_files(matches);
+    } else if matches.is_present("replaygain") {
+        replaygain::replaygain(matches);
+    } else if matches.is_present("check-album") {
+        album::check_album(matches);
+    } else if matches.is_present("lint") {
+        lint::lint(matches);
+    } else if matches.is_present("strip") {
+        strip::strip(matches);
+    } else if matches.is_present("remove-tags") {
+        untag::remove_tags(matches);
+    } else if matches.is_present("padding") || matches.is_present("compact") {
+        padding::padding(matches);
+    } else if matches.is_present("stats") {
+        stats::stats(matches);
     } else {
         read(matches);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xfe];
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn frame(id: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = id.as_bytes().to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn comm(text: &str) -> Vec<u8> {
+        let mut data = b"\x01eng".to_vec();
+        data.extend(utf16(""));
+        data.extend_from_slice(&[0, 0]);
+        data.extend(utf16(text));
+        frame("COMM", &data)
+    }
+
+    //
+    // A file of a version 3 tag with TIT2 and a UTF-16 COMM.
+    //
+    fn file(name: &str) -> PathBuf {
+        let mut tit2 = vec![1];
+        tit2.extend(utf16("Title"));
+
+        let mut frames = frame("TIT2", &tit2);
+        frames.extend(comm("Café 한"));
+
+        let size = frames.len() + 64;
+        let mut bytes = b"ID3\x03\x00\x00".to_vec();
+        bytes.extend_from_slice(&[(size >> 21) as u8 & 0x7f,
+                                  (size >> 14) as u8 & 0x7f,
+                                  (size >> 7) as u8 & 0x7f,
+                                  size as u8 & 0x7f]);
+        bytes.extend(frames);
+        bytes.resize(10 + size + 256, 0);
+
+        let name = format!("markdang-{}-{}.mp3", name, std::process::id());
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn comm_text(all: &All) -> Option<String> {
+        all.frames.iter().flatten().filter_map(|vf| match vf.body {
+            FrameBody::COMM(ref frame) => Some(frame.actual_text.clone()),
+            _ => None,
+        }).next()
+    }
+
+    #[test]
+    fn update_keeps_utf16_comm() {
+        let path = file("keep");
+
+        let mut all = load(&path).unwrap();
+        assert_eq!(comm_text(&all), Some("Café 한".to_string()));
+
+        if let Some(ref mut frames) = all.frames {
+            frames.retain(|vf| framebody_to_id(&vf.body, 4) != "TIT2");
+        }
+        update(&mut all, &artwork::Shrink::default(), Path::new("")).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let comm = comm("Café 한");
+        assert!(bytes.windows(comm.len()).any(|w| w == &comm[..]));
+        assert!(!bytes.windows(4).any(|w| w == b"TIT2"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn update_encodes_utf16_comm() {
+        let path = file("encode");
+
+        let mut all = load(&path).unwrap();
+        for vf in all.frames.iter_mut().flatten() {
+            if let FrameBody::COMM(ref mut frame) = vf.body {
+                frame.actual_text = "Nouvé 값".to_string();
+            }
+        }
+        update(&mut all, &artwork::Shrink::default(), Path::new("")).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        let comm = comm("Nouvé 값");
+        assert!(bytes.windows(comm.len()).any(|w| w == &comm[..]));
+        assert_eq!(comm_text(&load(&path).unwrap()), Some("Nouvé 값".to_string()));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    //
+    // A file of a tag of `version` with frames of (id, data). ids are of the version.
+    //
+    pub fn tagged(name: &str, version: u8, frames: &[(&str, Vec<u8>)]) -> PathBuf {
+        let mut bytes = Vec::new();
+        for &(id, ref data) in frames {
+            bytes.extend_from_slice(id.as_bytes());
+            match version {
+                2 => bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]),
+                3 => bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()),
+                _ => bytes.extend((0..4).rev().map(|i| (data.len() >> (7 * i)) as u8 & 0x7f)),
+            }
+            if version > 2 {
+                bytes.extend_from_slice(&[0, 0]);
+            }
+            bytes.extend_from_slice(data);
+        }
+
+        let mut tag = vec![b'I', b'D', b'3', version, 0, 0];
+        tag.extend((0..4).rev().map(|i| (bytes.len() >> (7 * i)) as u8 & 0x7f));
+        tag.extend(bytes);
+        tag.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        tag.resize(tag.len() + 413, 0);
+
+        let name = format!("markdang-{}-{}.mp3", name, std::process::id());
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, tag).unwrap();
+        path
+    }
+
+    fn strings(all: &All) -> Vec<String> {
+        all.frames
+            .iter()
+            .flatten()
+            .flat_map(|vf| tag::strings(&vf.body))
+            .map(|string| string.to_string())
+            .collect()
+    }
+
+    //
+    // Text, COMM and TXXX of `encoding` are read, written as they are, and written changed.
+    //
+    fn round_trip(version: u8, ids: [&str; 3], encoding: u8) {
+        let text = |text: &str| match encoding {
+            1 => utf16(text),
+            _ => text.as_bytes().to_vec(),
+        };
+        let end: &[u8] = if encoding == 1 { &[0, 0] } else { &[0] };
+
+        let mut title = vec![encoding];
+        title.extend(text("Hello 한"));
+
+        let mut comm = vec![encoding, b'e', b'n', b'g'];
+        comm.extend(text("Désc"));
+        comm.extend_from_slice(end);
+        comm.extend(text("Café 한"));
+
+        let mut txxx = vec![encoding];
+        txxx.extend(text("Kéy"));
+        txxx.extend_from_slice(end);
+        txxx.extend(text("Vàl 값"));
+
+        let name = format!("round-trip-{}-{}", version, encoding);
+        let path = tagged(&name, version, &[(ids[0], title), (ids[1], comm), (ids[2], txxx)]);
+        let read = vec!["Hello 한", "Désc", "Café 한", "Kéy", "Vàl 값"];
+
+        let mut all = load(&path).unwrap();
+        assert_eq!(strings(&all), read);
+
+        update(&mut all, &artwork::Shrink::default(), Path::new("")).unwrap();
+        let mut all = load(&path).unwrap();
+        assert_eq!(all.head.as_ref().map(|head| head.version.as_str()),
+                   Some(version.to_string().as_str()));
+        assert_eq!(strings(&all), read);
+
+        for vf in all.frames.iter_mut().flatten() {
+            match vf.body {
+                FrameBody::COMM(ref mut frame) => frame.actual_text = "Nouvé 값".to_string(),
+                FrameBody::TXXX(ref mut frame) => frame.value = "Vàl 2".to_string(),
+                _ => (),
+            }
+        }
+        update(&mut all, &artwork::Shrink::default(), Path::new("")).unwrap();
+        assert_eq!(strings(&load(&path).unwrap()),
+                   vec!["Hello 한", "Désc", "Nouvé 값", "Kéy", "Vàl 2"]);
+
+        let mut audio = vec![0xff, 0xfb, 0x90, 0x00];
+        audio.resize(4 + 413, 0);
+        assert!(fs::read(&path).unwrap().ends_with(&audio));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn round_trip_v22() {
+        round_trip(2, ["TT2", "COM", "TXX"], 1);
+    }
+
+    #[test]
+    fn round_trip_v23() {
+        round_trip(3, ["TIT2", "COMM", "TXXX"], 1);
+    }
+
+    #[test]
+    fn round_trip_v24() {
+        round_trip(4, ["TIT2", "COMM", "TXXX"], 1);
+        round_trip(4, ["TIT2", "COMM", "TXXX"], 3);
+    }
+
+    #[test]
+    fn ids_of_match() {
+        assert_eq!(match_ids("APIC.width<500 | APIC.format!'jpeg'"), vec!["APIC", "APIC"]);
+        assert_eq!(match_ids("MPEG.health='bad' & TIT2.text~'A.B'"), vec!["MPEG", "TIT2"]);
+        assert!(match_ids("TIT2").is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+use artwork;
+use clap;
+use tag;
+
+use encoding::{DecoderTrap, EncoderTrap, EncodingRef};
+use encoding::all::{BIG5_2003, GBK, WINDOWS_31J, WINDOWS_949};
+use rtag::frame::{FrameBody, framebody_to_id};
+use rtag::frame::types::TextEncoding;
+
+use std::path::{Path, PathBuf};
+
+use {All, fill, load, update};
+
+//
+// Code pages that are tried in order when it is guessed.
+//
+const CODEPAGES: [&str; 4] = ["cp949", "shift-jis", "gbk", "big5"];
+
+//
+// A ratio of characters in the script of a code page that a guessed text must have.
+//
+const MIN_SCORE: f32 = 0.8;
+
+fn codec(codepage: &str) -> Option<EncodingRef> {
+    match codepage.to_lowercase().replace('_', "-").as_str() {
+        "cp949" | "euc-kr" | "windows-949" => Some(WINDOWS_949),
+        "shift-jis" | "sjis" | "cp932" | "windows-31j" => Some(WINDOWS_31J),
+        "gbk" | "cp936" | "gb2312" => Some(GBK),
+        "big5" | "cp950" => Some(BIG5_2003),
+        _ => None,
+    }
+}
+
+fn in_script(codepage: &str, ch: char) -> bool {
+    let ch = ch as u32;
+    let punctuation = (0x3000..=0x303f).contains(&ch) || (0xff01..=0xff5e).contains(&ch);
+    let han = (0x4e00..=0x9fff).contains(&ch);
+
+    punctuation ||
+    match codepage {
+        "cp949" => (0x3130..=0x318f).contains(&ch) || (0xac00..=0xd7a3).contains(&ch) && common_hangul(ch),
+        //
+        // halfwidth katakana is rare, and it is what big5 and gbk look like in shift-jis.
+        //
+        "shift-jis" => han || (0x3040..=0x30ff).contains(&ch),
+        _ => han && common_han(codepage, ch),
+    }
+}
+
+//
+// 2350 hangul of EUC-KR. others of cp949 are rare, and they are what
+// other code pages look like in cp949. ex) "さくら" of shift-jis => "궠궘귞"
+//
+fn common_hangul(ch: u32) -> bool {
+    encoded(WINDOWS_949, ch).is_some_and(|bytes| bytes.iter().all(|b| *b >= 0xa1))
+}
+
+//
+// Common han of GBK and Big5, like `common_hangul`. GB2312 of GBK, and level 1 of Big5.
+// a latin letter with an ascii one looks like others. ex) "Motörhead" => "Mot鰎head" of GBK
+//
+fn common_han(codepage: &str, ch: u32) -> bool {
+    match codepage {
+        "gbk" => encoded(GBK, ch).is_some_and(|bytes| bytes.iter().all(|b| *b >= 0xa1)),
+        "big5" => encoded(BIG5_2003, ch).is_some_and(|bytes| (0xa4..=0xc6).contains(&bytes[0])),
+        _ => true,
+    }
+}
+
+fn encoded(codec: EncodingRef, ch: u32) -> Option<Vec<u8>> {
+    let ch = ::std::char::from_u32(ch)?.to_string();
+
+    codec.encode(&ch, EncoderTrap::Strict).ok()
+}
+
+//
+// Bytes of a text that is decoded in ISO-8859-1. None if it has no byte over 0x7f.
+//
+fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.chars().any(|ch| ch as u32 > 0xff) || text.is_ascii() {
+        return None;
+    }
+
+    Some(text.chars().map(|ch| ch as u8).collect())
+}
+
+//
+// "±è±¤¼®" => "김광석" for cp949
+//
+pub fn decode(text: &str, codepage: &str) -> Option<String> {
+    let bytes = latin1_bytes(text)?;
+
+    codec(codepage)?.decode(&bytes, DecoderTrap::Strict).ok()
+}
+
+fn score(codepage: &str, decoded: &str) -> f32 {
+    let wide: Vec<char> = decoded.chars().filter(|ch| !ch.is_ascii()).collect();
+    let hit = wide.iter().filter(|ch| in_script(codepage, **ch)).count();
+
+    hit as f32 / wide.len().max(1) as f32
+}
+
+//
+// (code page, decoded text) that is the most likely.
+//
+pub fn guess(text: &str) -> Option<(&'static str, String)> {
+    let mut ret: Option<(&'static str, String, f32)> = None;
+
+    for codepage in CODEPAGES.iter() {
+        let decoded = match decode(text, codepage) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+
+        let score = score(codepage, &decoded);
+        let better = match ret {
+            Some((_, _, best)) => score > best,
+            None => true,
+        };
+
+        if score >= MIN_SCORE && better {
+            ret = Some((codepage, decoded, score));
+        }
+    }
+
+    ret.map(|(codepage, decoded, _)| (codepage, decoded))
+}
+
+//
+// A code page of a text frame that looks mis-decoded. only ISO-8859-1 text frames keep
+// the original bytes, rtag decodes other strings in UTF-8 and invalid bytes are lost.
+//
+pub fn detect(fbody: &FrameBody) -> Option<&'static str> {
+    match tag::text_body(fbody) {
+        Some(frame) if frame.text_encoding == TextEncoding::ISO88591 => {
+            guess(&frame.text).map(|(codepage, _)| codepage)
+        }
+        _ => None,
+    }
+}
+
+fn repair_text(text: &str, codepage: &str) -> Option<(String, String)> {
+    if codepage == "auto" {
+        guess(text).map(|(codepage, decoded)| (codepage.to_string(), decoded))
+    } else {
+        decode(text, codepage).map(|decoded| (codepage.to_string(), decoded))
+    }
+}
+
+//
+// (changed, report)
+//
+fn repair_all(all: &mut All, codepage: &str) -> (bool, Vec<String>) {
+    let mut report = Vec::new();
+    let mut changed = false;
+
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => 4,
+    };
+
+    if let Some(ref mut frames) = all.frames {
+        for vf in frames.iter_mut() {
+            let text = match tag::text_body(&vf.body) {
+                Some(frame) if frame.text_encoding == TextEncoding::ISO88591 => frame.text.clone(),
+                _ => continue,
+            };
+
+            let (used, decoded) = match repair_text(&text, codepage) {
+                Some(repaired) => repaired,
+                None => continue,
+            };
+
+            let id = framebody_to_id(&vf.body, 4);
+            if let Some(fbody) = tag::text_frame(id, &decoded, version) {
+                report.push(format!("{}: \"{}\" -> \"{}\" ({})", id, text, decoded, used));
+                vf.body = fbody;
+                changed = true;
+            }
+        }
+    }
+
+    //
+    // ID3v1 has no unicode. repaired values go to version 2 frames, and frame1 is kept.
+    //
+    if let Some(frame1) = all.frame1.clone() {
+        for (id, value) in tag::frame1_texts(&frame1) {
+            let (used, decoded) = match repair_text(&value, codepage) {
+                Some(repaired) => repaired,
+                None => continue,
+            };
+
+            if fill(all, id, &decoded, version) {
+                report.push(format!("frame1 {}: \"{}\" -> \"{}\" ({})", id, value, decoded, used));
+                changed = true;
+            } else {
+                report.push(format!("frame1 {}: \"{}\" is \"{}\" ({}), {} already has a value",
+                                    id,
+                                    value,
+                                    decoded,
+                                    used,
+                                    id));
+            }
+        }
+    }
+
+    (changed, report)
+}
+
+pub fn repair(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+    let codepage = matches.value_of("fix-mojibake").unwrap();
+
+    if codepage != "auto" && codec(codepage).is_none() {
+        error!("Unknown code page: {}. (auto|cp949|euc-kr|shift-jis|gbk|big5)", codepage);
+        return;
+    }
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let (changed, report) = repair_all(&mut all, codepage);
+
+        if report.is_empty() {
+            debug!("no mojibake: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for line in report {
+            println!("\t{}", line);
+        }
+
+        if dry_run || !changed {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // A text in a code page that is decoded in ISO-8859-1.
+    //
+    fn mojibake(text: &str, codepage: &str) -> String {
+        codec(codepage).unwrap().encode(text, EncoderTrap::Strict).unwrap().iter().map(|b| *b as char).collect()
+    }
+
+    #[test]
+    fn guess_cp949() {
+        assert_eq!(mojibake("김광석", "cp949"), "±è±¤¼®");
+        assert_eq!(guess("±è±¤¼®"), Some(("cp949", "김광석".to_string())));
+        assert_eq!(decode("±è±¤¼®", "euc-kr"), Some("김광석".to_string()));
+    }
+
+    #[test]
+    fn guess_other_codepages() {
+        for &(text, codepage) in &[("さくら", "shift-jis"),
+                                   ("中島みゆき", "shift-jis"),
+                                   ("王菲", "gbk"),
+                                   ("周杰倫", "big5")] {
+            let garbled = mojibake(text, codepage);
+            assert_eq!(guess(&garbled), Some((codepage, text.to_string())), "{}", text);
+        }
+
+        //
+        // "さくら" is "궠궘귞" in cp949, that is not a common hangul.
+        //
+        let garbled = mojibake("さくら", "shift-jis");
+        assert_eq!(decode(&garbled, "cp949"), Some("궠궘귞".to_string()));
+        assert!(score("cp949", "궠궘귞") < MIN_SCORE);
+
+        //
+        // "周杰倫" of big5 is halfwidth katakana in shift-jis.
+        //
+        assert_eq!(decode(&mojibake("周杰倫", "big5"), "shift-jis"), Some("ｩPｪNｭﾛ".to_string()));
+        assert!(score("shift-jis", "ｩPｪNｭﾛ") < MIN_SCORE);
+    }
+
+    #[test]
+    fn keep_unicode_and_latin1() {
+        assert_eq!(guess("Café"), None);
+        assert_eq!(guess("Motörhead"), None);
+        assert_eq!(decode("Motörhead", "gbk"), Some("Mot鰎head".to_string()));
+        assert!(score("gbk", "Mot鰎head") < MIN_SCORE);
+        assert_eq!(guess("김광석"), None);
+        assert_eq!(guess("ASCII"), None);
+        assert_eq!(latin1_bytes("Café"), Some(vec![b'C', b'a', b'f', 0xe9]));
+        assert_eq!(latin1_bytes("ASCII"), None);
+    }
+
+    #[test]
+    fn hangul_of_euc_kr() {
+        assert!(common_hangul('김' as u32));
+        assert!(common_hangul('가' as u32));
+        assert!(!common_hangul('궠' as u32));
+        assert!(!common_hangul('A' as u32));
+
+        assert_eq!(score("cp949", "김광석"), 1.0);
+        assert_eq!(score("cp949", "김 Kim é"), 0.5);
+        assert_eq!(score("shift-jis", "さくら"), 1.0);
+        assert_eq!(score("gbk", "さくら"), 0.0);
+    }
+}
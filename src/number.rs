@@ -0,0 +1,335 @@
+use artwork;
+use clap;
+use copy;
+use tag;
+
+use regex::{Captures, Regex};
+use rtag::frame::{FrameBody, framebody_to_id, id};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use {All, load, update};
+
+//
+// A number and a total of TRCK or TPOS, and a field of a format. they are compiled once by
+// callers of `parse` and `render`.
+//
+pub const NUMBER: &str = r"^(\d+)\s*(?:(?:/|(?i:of))\s*(\d+))?$";
+const FIELD: &str = r"\{([nt])(?::(\d+))?\}";
+
+//
+// A number and a total of TRCK or TPOS. "3", "03", "3/12", "03 of 12" => (3, Some(12))
+//
+fn parse(re: &Regex, value: &str) -> Option<(u32, Option<u32>)> {
+    let caps = re.captures(value.trim())?;
+
+    let number = caps.get(1)?.as_str().parse().ok()?;
+    let total = caps.get(2).and_then(|total| total.as_str().parse().ok());
+
+    Some((number, total))
+}
+
+//
+// "{n:02}/{t:02}" => "03/12". without a total, the format is cut after `{n}`. => "03"
+//
+fn render(re: &Regex, format: &str, number: u32, total: Option<u32>) -> String {
+    let format = match (total, format.find("{t")) {
+        (None, Some(at)) => {
+            let end = format[..at].rfind('}').map(|end| end + 1).unwrap_or(0);
+            &format[..end]
+        }
+        _ => format,
+    };
+
+    re.replace_all(format, |caps: &Captures| {
+            let value = match &caps[1] {
+                "n" => number,
+                _ => total.unwrap_or(0),
+            };
+            let width = caps.get(2).and_then(|width| width.as_str().parse().ok()).unwrap_or(0);
+
+            format!("{:0width$}", value, width = width)
+        })
+        .to_string()
+}
+
+//
+// Tracks of an album.
+//
+pub struct Album {
+    //
+    // disc => track => files
+    //
+    discs: BTreeMap<u32, BTreeMap<u32, Vec<String>>>,
+    //
+    // the largest total of TRCK by disc
+    //
+    track_totals: HashMap<u32, u32>,
+    //
+    // the number of files by disc
+    //
+    counts: HashMap<u32, u32>,
+    disc_total: Option<u32>,
+}
+
+impl Album {
+    pub fn new(files: &[PathBuf], number: &Regex) -> Album {
+        let mut discs: BTreeMap<u32, BTreeMap<u32, Vec<String>>> = BTreeMap::new();
+        let mut track_totals = HashMap::new();
+        let mut counts = HashMap::new();
+        let mut disc_total = None;
+
+        for file in files {
+            let texts = tag::texts(file).unwrap_or_default();
+
+            let disc = match texts.get(id::TPOS).and_then(|v| parse(number, v)) {
+                Some((disc, total)) => {
+                    disc_total = disc_total.max(total).max(Some(disc));
+                    disc
+                }
+                None => 1,
+            };
+
+            *counts.entry(disc).or_insert(0) += 1;
+            let tracks = discs.entry(disc).or_default();
+
+            if let Some((track, total)) = texts.get(id::TRCK).and_then(|v| parse(number, v)) {
+                if let Some(total) = total {
+                    let max = track_totals.entry(disc).or_insert(0);
+                    *max = total.max(*max);
+                }
+
+                let name = file.file_name().map(|name| name.to_string_lossy().to_string());
+                tracks.entry(track).or_default().extend(name);
+            }
+        }
+
+        Album {
+            discs,
+            track_totals,
+            counts,
+            disc_total,
+        }
+    }
+
+    //
+    // the largest total of TRCK on a disc, or the number of files if no file has a total.
+    //
+    fn track_total(&self, disc: u32) -> Option<u32> {
+        self.track_totals.get(&disc).or_else(|| self.counts.get(&disc)).cloned()
+    }
+
+    //
+    // Duplicate or missing track numbers. a track total is the largest total or track number.
+    //
+    pub fn report(&self) -> Vec<String> {
+        let mut report = Vec::new();
+        let multi_disc = self.discs.len() > 1;
+
+        for (disc, tracks) in &self.discs {
+            let prefix = if multi_disc { format!("disc {} ", disc) } else { String::new() };
+
+            for (track, files) in tracks {
+                if files.len() > 1 {
+                    report.push(format!("{}duplicate track {}: {}", prefix, track, files.join(", ")));
+                }
+            }
+
+            let last = tracks.keys().last().cloned().unwrap_or(0);
+            let total = self.track_totals.get(disc).cloned().unwrap_or(0).max(last);
+            let missing: Vec<String> = (1..=total)
+                .filter(|track| !tracks.contains_key(track))
+                .map(|track| track.to_string())
+                .collect();
+
+            if !missing.is_empty() {
+                report.push(format!("{}missing track {}", prefix, missing.join(", ")));
+            }
+        }
+
+        if let Some(disc_total) = self.disc_total {
+            let missing: Vec<String> = (1..=disc_total)
+                .filter(|disc| !self.discs.contains_key(disc))
+                .map(|disc| disc.to_string())
+                .collect();
+
+            if !missing.is_empty() {
+                report.push(format!("missing disc {}", missing.join(", ")));
+            }
+        }
+
+        report
+    }
+}
+
+//
+// (changed, report). a missing total is filled from `album`.
+//
+fn normalize_all(all: &mut All,
+                 format: &str,
+                 album: Option<&Album>,
+                 number_re: &Regex,
+                 field_re: &Regex)
+                 -> (bool, Vec<String>) {
+    let mut changed = false;
+    let mut report = Vec::new();
+
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => return (changed, report),
+    };
+
+    let frames = match all.frames {
+        Some(ref mut frames) => frames,
+        None => return (changed, report),
+    };
+
+    let disc = frames.iter()
+        .find(|vf| framebody_to_id(&vf.body, 4) == id::TPOS)
+        .and_then(|vf| tag::text_of(&vf.body))
+        .and_then(|(_, value)| parse(number_re, &value))
+        .map(|(disc, _)| disc)
+        .unwrap_or(1);
+
+    for vf in frames.iter_mut() {
+        let id = match vf.body {
+            FrameBody::TRCK(_) => id::TRCK,
+            FrameBody::TPOS(_) => id::TPOS,
+            _ => continue,
+        };
+
+        let value = match tag::text_of(&vf.body) {
+            Some((_, value)) => value,
+            None => continue,
+        };
+
+        let (number, total) = match parse(number_re, &value) {
+            Some(parsed) => parsed,
+            None => {
+                report.push(format!("{}: can not parse \"{}\"", id, value));
+                continue;
+            }
+        };
+
+        let total = total.or_else(|| {
+            album.and_then(|album| if id == id::TRCK {
+                album.track_total(disc)
+            } else {
+                album.disc_total
+            })
+        });
+
+        let normalized = render(field_re, format, number, total);
+        if normalized == value {
+            continue;
+        }
+
+        if let Some(fbody) = tag::text_frame(id, &normalized, version) {
+            report.push(format!("{}: \"{}\" -> \"{}\"", id, value, normalized));
+            vf.body = fbody;
+            changed = true;
+        }
+    }
+
+    (changed, report)
+}
+
+pub fn normalize(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+    let fill_total = matches.is_present("fill-total");
+    let format = matches.value_of("normalize-number").unwrap();
+
+    if !format.contains("{n") {
+        error!("No {{n}} in a format: {}. ex) {{n:02}}/{{t:02}}", format);
+        return;
+    }
+
+    //
+    // files by an album directory
+    //
+    let mut dirs: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+
+    for file in files {
+        match PathBuf::from(file).canonicalize() {
+            Ok(path) => {
+                let dir = path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+                dirs.entry(dir).or_default().insert(path);
+            }
+            Err(e) => error!("{}: {:?}", file, e),
+        }
+    }
+
+    let number_re = Regex::new(NUMBER).unwrap();
+    let field_re = Regex::new(FIELD).unwrap();
+
+    for (dir, paths) in dirs {
+        let album = Album::new(&copy::files_of(&dir), &number_re);
+
+        for path in paths {
+            let mut all = match load(&path) {
+                Some(all) => all,
+                None => continue,
+            };
+
+            let fill = if fill_total { Some(&album) } else { None };
+            let (changed, report) = normalize_all(&mut all, format, fill, &number_re, &field_re);
+
+            if report.is_empty() {
+                debug!("already normalized: {:?}", path);
+                continue;
+            }
+
+            println!("{}", path.display());
+            for line in report {
+                println!("\t{}", line);
+            }
+
+            if dry_run || !changed {
+                continue;
+            }
+
+            if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+                error!("Can not write: {:?}, {:?}", path, e);
+            }
+        }
+
+        let report = album.report();
+        if !report.is_empty() {
+            println!("{}", dir.display());
+            for line in report {
+                println!("\t{}", line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_numbers() {
+        let re = Regex::new(NUMBER).unwrap();
+
+        assert_eq!(parse(&re, "3"), Some((3, None)));
+        assert_eq!(parse(&re, " 03/12 "), Some((3, Some(12))));
+        assert_eq!(parse(&re, "3 / 12"), Some((3, Some(12))));
+        assert_eq!(parse(&re, "03 OF 12"), Some((3, Some(12))));
+        assert_eq!(parse(&re, "3/"), None);
+        assert_eq!(parse(&re, "A3"), None);
+        assert_eq!(parse(&re, ""), None);
+    }
+
+    #[test]
+    fn render_formats() {
+        let re = Regex::new(FIELD).unwrap();
+
+        assert_eq!(render(&re, "{n:02}/{t:02}", 3, Some(12)), "03/12");
+        assert_eq!(render(&re, "{n}/{t}", 3, Some(12)), "3/12");
+        assert_eq!(render(&re, "{n:02} of {t}", 3, Some(12)), "03 of 12");
+        assert_eq!(render(&re, "{n:02}/{t:02}", 3, None), "03");
+        assert_eq!(render(&re, "{n:03}", 3, Some(12)), "003");
+    }
+}
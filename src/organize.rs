@@ -0,0 +1,380 @@
+use clap;
+use tag;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//
+// A part of a path template.
+// ex) "{TPE2|TPE1}/{TDRC:year} - {TALB}/{TRCK:02} {TIT2}.mp3"
+//
+#[derive(Debug)]
+enum Part {
+    Text(String),
+    Field(Vec<String>, Option<String>), // ids, modifier
+    Separator,
+}
+
+fn parse(template: &str) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => {
+                if !text.is_empty() {
+                    parts.push(Part::Text(text.clone()));
+                    text.clear();
+                }
+
+                let field: String = chars.by_ref().take_while(|ch| *ch != '}').collect();
+                //
+                // ':' is also in a TXXX id. ex) {TXXX:CATALOGNUMBER:upper}
+                //
+                let (ids, modifier) = match field.rfind(':') {
+                    Some(idx) if is_modifier(&field[idx + 1..]) => {
+                        (&field[..idx], Some(field[idx + 1..].to_string()))
+                    }
+                    _ => (field.as_str(), None),
+                };
+
+                parts.push(Part::Field(ids.split('|').map(|id| id.trim().to_string()).collect(),
+                                       modifier));
+            }
+            '/' | '\\' => {
+                if !text.is_empty() {
+                    parts.push(Part::Text(text.clone()));
+                    text.clear();
+                }
+                parts.push(Part::Separator);
+            }
+            _ => text.push(ch),
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+
+    parts
+}
+
+fn is_modifier(modifier: &str) -> bool {
+    match modifier {
+        "year" | "lower" | "upper" => true,
+        width => !width.is_empty() && width.chars().all(|ch| ch.is_ascii_digit()),
+    }
+}
+
+fn modify(value: &str, modifier: &Option<String>) -> String {
+    let modifier = match *modifier {
+        Some(ref modifier) => modifier.as_str(),
+        None => return value.to_string(),
+    };
+
+    match modifier {
+        "year" => value.chars().take(4).collect(),
+        "lower" => value.to_lowercase(),
+        "upper" => value.to_uppercase(),
+        width if is_modifier(width) => {
+            //
+            // "3/12" => "03"
+            //
+            let number: String = value.trim()
+                .chars()
+                .take_while(|ch| ch.is_ascii_digit())
+                .collect();
+
+            match number.parse::<u32>() {
+                Ok(number) => format!("{:0width$}", number, width = width.parse().unwrap_or(0)),
+                Err(_) => value.to_string(),
+            }
+        }
+        _ => {
+            warn!("Unknown modifier: {}", modifier);
+            value.to_string()
+        }
+    }
+}
+
+//
+// Characters that are not allowed on FAT or SMB shares.
+//
+pub fn sanitize(value: &str) -> String {
+    value.chars()
+        .map(|ch| match ch {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect()
+}
+
+fn tidy_segment(segment: &str) -> String {
+    const RESERVED: [&str; 22] = ["CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4",
+                                  "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3",
+                                  "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9"];
+
+    let segment = segment.split_whitespace().collect::<Vec<_>>().join(" ");
+    let segment = segment.trim_matches(|ch: char| ch == ' ' || ch == '-' || ch == '_' || ch == '.');
+
+    if segment.is_empty() {
+        return "Unknown".to_string();
+    }
+
+    let stem = segment.split('.').next().unwrap_or("").to_uppercase();
+    if RESERVED.contains(&stem.as_str()) {
+        return format!("_{}", segment);
+    }
+
+    segment.to_string()
+}
+
+//
+// It computes a relative path from a template. a missing field becomes empty, and a separator
+// next to it is dropped. "{TPE1} - {TALB} - {TIT2}" without TALB => "Artist - Title"
+//
+fn render(template: &[Part], texts: &HashMap<String, String>, ext: &str) -> PathBuf {
+    //
+    // (is a field, value) of each segment
+    //
+    let mut pieces: Vec<Vec<(bool, String)>> = vec![Vec::new()];
+
+    for part in template {
+        match *part {
+            Part::Text(ref text) => pieces.last_mut().unwrap().push((false, text.clone())),
+            Part::Field(ref ids, ref modifier) => {
+                let value = ids.iter()
+                    .filter_map(|id| texts.get(id))
+                    .map(|value| value.trim())
+                    .find(|value| !value.is_empty())
+                    .unwrap_or("");
+
+                pieces.last_mut().unwrap().push((true, sanitize(&modify(value, modifier))));
+            }
+            Part::Separator => pieces.push(Vec::new()),
+        }
+    }
+
+    let segments: Vec<String> = pieces.into_iter().map(collapse).collect();
+
+    let mut path = PathBuf::new();
+    let last = segments.len() - 1;
+
+    for (i, segment) in segments.iter().enumerate() {
+        if i == last {
+            let (stem, file_ext) = match segment.rfind('.') {
+                Some(idx) if segment[idx + 1..].eq_ignore_ascii_case(ext) => {
+                    (&segment[..idx], &segment[idx + 1..])
+                }
+                _ => (segment.as_str(), ext),
+            };
+            path.push(format!("{}.{}", tidy_segment(stem), file_ext));
+        } else {
+            path.push(tidy_segment(segment));
+        }
+    }
+
+    path
+}
+
+//
+// A segment without empty fields and separators of them. a separator is a text of spaces and
+// punctuation, that is before an empty field, or after it at the start of a segment.
+// brackets around an empty field are dropped too. "{TIT2} ({TDRC:year})" => "Title"
+//
+fn collapse(pieces: Vec<(bool, String)>) -> String {
+    let separator = |text: &str| text.chars().all(|ch| ch.is_whitespace() || "-_.,;~".contains(ch));
+
+    let mut kept: Vec<(bool, String)> = Vec::new();
+    let mut at_start = false;
+    let mut close = None;
+
+    for (field, mut value) in pieces {
+        if field && value.is_empty() {
+            match kept.last_mut() {
+                Some(&mut (false, ref mut text)) if text.ends_with('(') || text.ends_with('[') => {
+                    close = if text.pop() == Some('(') { Some(')') } else { Some(']') };
+                    let len = text.trim_end().len();
+                    text.truncate(len);
+                }
+                Some(&mut (false, ref text)) if separator(text) => {
+                    kept.pop();
+                }
+                None => at_start = true,
+                _ => (),
+            }
+            continue;
+        }
+
+        if let Some(close) = close.take() {
+            if !field && value.starts_with(close) {
+                value.remove(0);
+            }
+        }
+
+        if at_start && !field && separator(&value) {
+            at_start = false;
+            continue;
+        }
+
+        at_start = false;
+        kept.push((field, value));
+    }
+
+    kept.into_iter().map(|(_, value)| value).collect()
+}
+
+//
+// "Title.mp3" => "Title (1).mp3"
+//
+fn resolve_collision(path: PathBuf, planned: &HashSet<PathBuf>) -> PathBuf {
+    if !path.exists() && !planned.contains(&path) {
+        return path;
+    }
+
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("").to_string();
+
+    let mut i = 1;
+    loop {
+        let candidate = path.with_file_name(format!("{} ({}).{}", stem, i, ext));
+        if !candidate.exists() && !planned.contains(&candidate) {
+            return candidate;
+        }
+        i += 1;
+    }
+}
+
+fn move_file(from: &Path, to: &Path, copy: bool) -> ::std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if copy {
+        return fs::copy(from, to).map(|_| ());
+    }
+
+    //
+    // rename does not work across file systems.
+    //
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+pub fn organize(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let template = parse(matches.value_of("organize").unwrap());
+    let target = PathBuf::from(matches.value_of("target-dir").unwrap_or("."));
+    let dry_run = matches.is_present("dry-run");
+    let copy = matches.is_present("copy");
+
+    let mut planned = HashSet::new();
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let texts = match tag::texts(&path) {
+            Some(texts) => texts,
+            None => continue,
+        };
+
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("mp3");
+        let dest = target.join(render(&template, &texts, ext));
+
+        if dest == path || dest.canonicalize().ok() == Some(path.clone()) {
+            debug!("same path: {:?}", path);
+            continue;
+        }
+
+        let dest = resolve_collision(dest, &planned);
+
+        println!("{} -> {}", path.display(), dest.display());
+
+        if !dry_run {
+            if let Err(e) = move_file(&path, &dest, copy) {
+                error!("Can not move {:?} to {:?}: {:?}", path, dest, e);
+                continue;
+            }
+        }
+
+        planned.insert(dest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(id, value)| (id.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn parse_template() {
+        let parts = parse("{TPE2|TPE1}/{TXXX:CATALOGNUMBER:upper} - {TRCK:02}.mp3");
+
+        match parts.as_slice() {
+            [Part::Field(ids, None), Part::Separator, Part::Field(txxx, Some(upper)),
+             Part::Text(dash), Part::Field(trck, Some(width)), Part::Text(ext)] => {
+                assert_eq!(ids, &["TPE2", "TPE1"]);
+                assert_eq!(txxx, &["TXXX:CATALOGNUMBER"]);
+                assert_eq!(upper, "upper");
+                assert_eq!(dash, " - ");
+                assert_eq!(trck, &["TRCK"]);
+                assert_eq!(width, "02");
+                assert_eq!(ext, ".mp3");
+            }
+            parts => panic!("{:?}", parts),
+        }
+    }
+
+    #[test]
+    fn render_path() {
+        let template = parse("{TPE2|TPE1}/{TDRC:year} - {TALB}/{TRCK:02} {TIT2}.mp3");
+        let texts = texts(&[("TPE1", "AC/DC"),
+                            ("TDRC", "1980-07-25"),
+                            ("TALB", "Back in Black"),
+                            ("TRCK", "6/10"),
+                            ("TIT2", "Back in Black")]);
+
+        assert_eq!(render(&template, &texts, "mp3"),
+                   PathBuf::from("AC_DC/1980 - Back in Black/06 Back in Black.mp3"));
+    }
+
+    #[test]
+    fn render_missing_and_reserved() {
+        let template = parse("{TPE1}/{TDRC:year} - {TALB}/{TIT2}.MP3");
+        let texts = texts(&[("TALB", "Con")]);
+
+        assert_eq!(render(&template, &texts, "mp3"), PathBuf::from("Unknown/_Con/Unknown.MP3"));
+    }
+
+    #[test]
+    fn render_empty_fields() {
+        let template = parse("{TPE1} - {TALB} - {TIT2}/{TRCK:02} {TIT2} ({TDRC:year}).mp3");
+
+        assert_eq!(render(&template, &texts(&[("TPE1", "A"), ("TIT2", "B")]), "mp3"),
+                   PathBuf::from("A - B/B.mp3"));
+        assert_eq!(render(&template, &texts(&[("TALB", "A"), ("TRCK", "1"), ("TDRC", "2001")]), "mp3"),
+                   PathBuf::from("A/01 (2001).mp3"));
+
+        let template = parse("{TPE1} - {TALB} - {TIT2}");
+        assert_eq!(render(&template, &HashMap::new(), "mp3"), PathBuf::from("Unknown.mp3"));
+
+        let template = parse("[{TPE1}] {TIT2}");
+        assert_eq!(render(&template, &texts(&[("TIT2", "B")]), "mp3"), PathBuf::from("B.mp3"));
+    }
+}
@@ -0,0 +1,324 @@
+use audio;
+use clap;
+use untag;
+
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+//
+// A size of a tag is 28 bits of synchsafe integer.
+//
+const MAX_TAG_SIZE: usize = 0x0fff_ffff;
+
+//
+// (frames, padding) of an ID3v2 tag. None if padding can not be changed.
+//
+fn id3v2(path: &Path, bytes: &[u8]) -> Option<(usize, usize)> {
+    let layout = audio::layout(bytes);
+
+    let (version, flags) = match layout.id3v2 {
+        Some(id3v2) => id3v2,
+        None => {
+            debug!("no ID3v2: {:?}", path);
+            return None;
+        }
+    };
+
+    //
+    // padding is not allowed with a footer, and an extended header of version 3 has a padding size.
+    // frames of an unsynchronised tag can not be walked in bytes.
+    //
+    if flags & 0x10 != 0 || (version == 3 && flags & 0x40 != 0) {
+        error!("Padding of a tag with a footer or an extended header is not changed: {:?}",
+               path);
+        return None;
+    }
+
+    if flags & 0x80 != 0 {
+        error!("Padding of an unsynchronised tag is not changed: {:?}", path);
+        return None;
+    }
+
+    let size = bytes[6..10].iter().fold(0usize, |size, b| (size << 7) | (*b as usize & 0x7f));
+    let end = (10 + size).min(bytes.len());
+
+    //
+    // zeros after a tag are padding too, but another tag is not.
+    //
+    if bytes[end..layout.id3v2_len].iter().any(|b| *b != 0) {
+        error!("More than one ID3v2 tag: {:?}", path);
+        return None;
+    }
+
+    let frames = audio::id3v2_frames_len(&bytes[10..end], version, flags);
+
+    //
+    // a frame that can not be walked would be lost with padding.
+    //
+    if bytes[10 + frames..end].iter().any(|b| *b != 0) {
+        error!("Bytes after the last frame are not padding: {:?}", path);
+        return None;
+    }
+
+    Some((frames, layout.id3v2_len - 10 - frames))
+}
+
+//
+// A tag with frames of a tag and zeros of `padding`.
+//
+fn render(bytes: &[u8], frames: usize, padding: usize) -> Option<Vec<u8>> {
+    let size = frames + padding;
+    if size > MAX_TAG_SIZE {
+        warn!("Too large padding: {}", padding);
+        return None;
+    }
+
+    let mut tag = bytes[..6].to_vec();
+    tag.extend_from_slice(&[(size >> 21) as u8 & 0x7f,
+                            (size >> 14) as u8 & 0x7f,
+                            (size >> 7) as u8 & 0x7f,
+                            size as u8 & 0x7f]);
+    tag.extend_from_slice(&bytes[10..10 + frames]);
+    tag.resize(10 + size, 0);
+
+    Some(tag)
+}
+
+//
+// It writes `tag` over ID3v2 tags of `bytes` that are `old_len`, and `frame1` over ID3v1.
+// a tag of the same length is written in place, and a file is rewritten if not.
+// true if it is in place.
+//
+fn put(path: &Path,
+       bytes: &[u8],
+       old_len: usize,
+       tag: &[u8],
+       frame1: Option<&[u8]>)
+       -> ::std::io::Result<bool> {
+    let has_frame1 = audio::layout(bytes).trailing.first().is_some_and(|t| t.0 == "ID3v1");
+
+    if tag.len() == old_len {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.write_all(tag)?;
+
+        if let Some(frame1) = frame1 {
+            if has_frame1 {
+                file.seek(SeekFrom::End(-128))?;
+            } else {
+                file.seek(SeekFrom::End(0))?;
+            }
+            file.write_all(frame1)?;
+        }
+        return Ok(true);
+    }
+
+    let end = if has_frame1 && frame1.is_some() { bytes.len() - 128 } else { bytes.len() };
+
+    let mut out = tag.to_vec();
+    out.extend_from_slice(&bytes[old_len..end]);
+    out.extend_from_slice(frame1.unwrap_or_default());
+
+    untag::replace(path, &out).map(|_| false)
+}
+
+//
+// Bytes of the first ID3v2 tag with zeros after it. a tag after it is not counted.
+//
+fn first_len(bytes: &[u8]) -> usize {
+    let layout = audio::layout(bytes);
+
+    let flags = match layout.id3v2 {
+        Some((_, flags)) => flags,
+        None => return 0,
+    };
+
+    let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+    let mut end = (10 + audio::syncsafe_len(&bytes[6..10]) + footer).min(layout.id3v2_len);
+
+    while end < layout.id3v2_len && bytes[end] == 0 {
+        end += 1;
+    }
+
+    end
+}
+
+//
+// It writes a tag of `rtag` in space of the first tag, if the tag fits in it. the rest is padding.
+// a tag that does not fit is written without padding. another tag after it is kept.
+//
+pub fn write_tag(path: &Path, tag: &[u8], frame1: Option<&[u8]>) -> ::std::io::Result<()> {
+    let bytes = fs::read(path)?;
+    let old_len = first_len(&bytes);
+
+    let padded = if tag.len() <= old_len && tag.len() >= 10 {
+        render(tag, tag.len() - 10, old_len - tag.len())
+    } else {
+        None
+    };
+
+    let in_place = put(path, &bytes, old_len, padded.as_ref().map_or(tag, |tag| tag), frame1)?;
+    debug!("write a tag: {:?}, in place {}", path, in_place);
+
+    Ok(())
+}
+
+fn report(path: &Path, bytes: &[u8]) {
+    let layout = audio::layout(bytes);
+
+    match layout.id3v2 {
+        Some((version, _)) => {
+            println!("{}\tID3v2.{}\ttag {} bytes\tpadding {} bytes",
+                     path.display(),
+                     version,
+                     layout.id3v2_len,
+                     layout.padding)
+        }
+        None => println!("{}\tno ID3v2", path.display()),
+    }
+}
+
+pub fn padding(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    //
+    // None is a report.
+    //
+    let target = if matches.is_present("compact") {
+        Some(0)
+    } else {
+        match matches.value_of("padding") {
+            Some("report") => None,
+            Some(value) => {
+                match value.parse::<usize>() {
+                    Ok(padding) => Some(padding),
+                    Err(_) => {
+                        error!("Invalid padding: {}. (report|<bytes>)", value);
+                        return;
+                    }
+                }
+            }
+            None => None,
+        }
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let bytes = match audio::read(&path) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+
+        let target = match target {
+            Some(target) => target,
+            None => {
+                report(&path, &bytes);
+                continue;
+            }
+        };
+
+        let (frames, padding) = match id3v2(&path, &bytes) {
+            Some(id3v2) => id3v2,
+            None => continue,
+        };
+
+        if padding == target {
+            debug!("same padding: {:?}", path);
+            continue;
+        }
+
+        let tag = match render(&bytes, frames, target) {
+            Some(tag) => tag,
+            None => continue,
+        };
+
+        let old_len = 10 + frames + padding;
+
+        println!("{}", path.display());
+        println!("\tpadding: {} => {} bytes", padding, target);
+
+        if dry_run {
+            continue;
+        }
+
+        if let Err(e) = put(&path, &bytes, old_len, &tag, None) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // A version 3 tag of TIT2 "Title", `padding` zeros in the tag, and `after` zeros after it.
+    //
+    fn tag(flags: u8, padding: usize, after: usize) -> Vec<u8> {
+        let frame = b"TIT2\x00\x00\x00\x06\x00\x00\x00Title";
+        let size = frame.len() + padding;
+
+        let mut bytes = vec![b'I', b'D', b'3', 3, 0, flags];
+        bytes.extend((0..4).rev().map(|i| (size >> (7 * i)) as u8 & 0x7f));
+        bytes.extend_from_slice(frame);
+        bytes.extend(vec![0; padding + after]);
+        bytes.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        bytes
+    }
+
+    #[test]
+    fn id3v2_frames_and_padding() {
+        let path = Path::new("test.mp3");
+
+        assert_eq!(id3v2(path, &tag(0, 20, 0)), Some((16, 20)));
+        assert_eq!(id3v2(path, &tag(0, 20, 5)), Some((16, 25)));
+        assert_eq!(id3v2(path, &tag(0, 0, 0)), Some((16, 0)));
+
+        assert_eq!(id3v2(path, &tag(0x10, 20, 0)), None);
+        assert_eq!(id3v2(path, &tag(0x80, 20, 0)), None);
+        assert_eq!(id3v2(path, &[0xff, 0xfb, 0x90, 0x00]), None);
+
+        let mut junk = tag(0, 20, 0);
+        junk[10 + 16 + 3] = b'x';
+        assert_eq!(id3v2(path, &junk), None);
+
+        let mut two = tag(0, 20, 0);
+        two.truncate(10 + 16 + 20);
+        two.extend(tag(0, 0, 0));
+        assert_eq!(id3v2(path, &two), None);
+    }
+
+    #[test]
+    fn first_tag_len() {
+        assert_eq!(first_len(&tag(0, 20, 5)), 10 + 16 + 25);
+        assert_eq!(first_len(&[0xff, 0xfb, 0x90, 0x00]), 0);
+
+        let mut two = tag(0, 20, 0);
+        two.truncate(10 + 16 + 20);
+        two.extend(tag(0, 0, 0));
+        assert_eq!(first_len(&two), 10 + 16 + 20);
+    }
+
+    #[test]
+    fn render_padding() {
+        let bytes = tag(0, 20, 0);
+
+        let rendered = render(&bytes, 16, 100).unwrap();
+        assert_eq!(rendered.len(), 10 + 16 + 100);
+        assert_eq!(&rendered[..6], &bytes[..6]);
+        assert_eq!(audio::syncsafe_len(&rendered[6..10]), 116);
+        assert_eq!(&rendered[10..26], &bytes[10..26]);
+        assert!(rendered[26..].iter().all(|b| *b == 0));
+
+        assert_eq!(render(&bytes, 16, 0).unwrap().len(), 26);
+        assert_eq!(render(&bytes, 16, MAX_TAG_SIZE), None);
+    }
+}
@@ -0,0 +1,260 @@
+use artwork;
+use clap;
+use tag;
+
+use rtag::frame::{FrameBody, framebody_to_id};
+use rtag::frame::types::TextEncoding;
+
+use std::path::{Path, PathBuf};
+
+use {All, load, update};
+
+fn to_encoding(name: &str) -> Option<TextEncoding> {
+    let name: String = name.chars()
+        .filter(|ch| ch.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    match name.as_str() {
+        "iso88591" | "latin1" => Some(TextEncoding::ISO88591),
+        "utf16" | "utf16le" => Some(TextEncoding::UTF16LE),
+        "utf16be" => Some(TextEncoding::UTF16BE),
+        "utf8" => Some(TextEncoding::UTF8),
+        _ => None,
+    }
+}
+
+//
+// Why a frame can not be in `encoding`, if it can't.
+//
+fn unfit(fbody: &FrameBody, encoding: &TextEncoding) -> Option<&'static str> {
+    let latin1 = tag::strings(fbody).iter().all(|text| text.chars().all(|ch| (ch as u32) < 0x100));
+
+    if tag::encodes(fbody) {
+        if *encoding == TextEncoding::ISO88591 && !latin1 {
+            return Some("not in ISO-8859-1");
+        }
+        return None;
+    }
+
+    //
+    // see `tag::legacy_encoding`
+    //
+    match *encoding {
+        TextEncoding::UTF16LE | TextEncoding::UTF16BE => {
+            Some("UTF-16 can not be written for COMR, OWNE and SYLT")
+        }
+        TextEncoding::ISO88591 if !tag::is_ascii(fbody) => Some("not ASCII"),
+        _ => None,
+    }
+}
+
+//
+// (changed, report)
+//
+fn reencode_all(all: &mut All, encoding: &TextEncoding) -> (bool, Vec<String>) {
+    let mut changed = false;
+    let mut report = Vec::new();
+
+    let frames = match all.frames {
+        Some(ref mut frames) => frames,
+        None => return (changed, report),
+    };
+
+    for vf in frames.iter_mut() {
+        let id = framebody_to_id(&vf.body, 4);
+        let reason = unfit(&vf.body, encoding);
+
+        let current = match tag::encoding_mut(&mut vf.body) {
+            Some(current) => current,
+            None => continue,
+        };
+
+        if *current == *encoding {
+            continue;
+        }
+
+        match reason {
+            Some(reason) => report.push(format!("kept {} {:?}: {}", id, current, reason)),
+            None => {
+                report.push(format!("{}: {:?} -> {:?}", id, current, encoding));
+                *current = encoding.clone();
+                changed = true;
+            }
+        }
+    }
+
+    (changed, report)
+}
+
+pub fn reencode(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    let encoding = match to_encoding(matches.value_of("encoding").unwrap()) {
+        Some(encoding) => encoding,
+        None => {
+            error!("Unknown encoding: {}. (iso88591|utf16|utf16be|utf8)",
+                   matches.value_of("encoding").unwrap());
+            return;
+        }
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        //
+        // UTF-8 and UTF-16BE are only for version 4.
+        //
+        let version = match all.head {
+            Some(ref head) => head.version.clone(),
+            None => continue,
+        };
+
+        if version != "4" && (encoding == TextEncoding::UTF8 || encoding == TextEncoding::UTF16BE) {
+            error!("There is no {:?} in version {}, convert it first. ex) --convert=4: {:?}",
+                   encoding,
+                   version,
+                   path);
+            continue;
+        }
+
+        let (changed, report) = reencode_all(&mut all, &encoding);
+
+        if report.is_empty() {
+            debug!("nothing to re-encode: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for line in report {
+            println!("\t{}", line);
+        }
+
+        if dry_run || !changed {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtag::frame::{OWNE, SYLT};
+    use rtag::frame::types::{ContentType, TimestampFormat};
+    use {ViewFrame, ViewHead};
+
+    fn owne(seller: &str) -> FrameBody {
+        FrameBody::OWNE(OWNE {
+            text_encoding: TextEncoding::ISO88591,
+            price_paid: "USD1.00".to_string(),
+            date_of_purch: "20010502".to_string(),
+            seller: seller.to_string(),
+        })
+    }
+
+    fn sylt() -> FrameBody {
+        FrameBody::SYLT(SYLT {
+            text_encoding: TextEncoding::ISO88591,
+            language: "eng".to_string(),
+            timestamp_format: TimestampFormat::Milliseconds,
+            content_type: ContentType::Lyrics,
+            content_descriptor: "la la".to_string(),
+        })
+    }
+
+    fn text(id: &str, value: &str) -> FrameBody {
+        tag::text_frame(id, value, 4).unwrap()
+    }
+
+    #[test]
+    fn names_of_encodings() {
+        assert_eq!(to_encoding("ISO-8859-1"), Some(TextEncoding::ISO88591));
+        assert_eq!(to_encoding("latin1"), Some(TextEncoding::ISO88591));
+        assert_eq!(to_encoding("utf16"), Some(TextEncoding::UTF16LE));
+        assert_eq!(to_encoding("UTF-16LE"), Some(TextEncoding::UTF16LE));
+        assert_eq!(to_encoding("utf-16be"), Some(TextEncoding::UTF16BE));
+        assert_eq!(to_encoding("UTF8"), Some(TextEncoding::UTF8));
+        assert_eq!(to_encoding("cp949"), None);
+    }
+
+    #[test]
+    fn unfit_frames() {
+        assert_eq!(unfit(&text("TIT2", "김광석"), &TextEncoding::ISO88591),
+                   Some("not in ISO-8859-1"));
+        assert_eq!(unfit(&text("TIT2", "Café"), &TextEncoding::ISO88591), None);
+        assert_eq!(unfit(&text("TIT2", "김광석"), &TextEncoding::UTF16LE), None);
+
+        for fbody in &[owne("Shop"), sylt()] {
+            assert_eq!(unfit(fbody, &TextEncoding::UTF16LE),
+                       Some("UTF-16 can not be written for COMR, OWNE and SYLT"));
+            assert_eq!(unfit(fbody, &TextEncoding::UTF16BE),
+                       Some("UTF-16 can not be written for COMR, OWNE and SYLT"));
+            assert_eq!(unfit(fbody, &TextEncoding::ISO88591), None);
+            assert_eq!(unfit(fbody, &TextEncoding::UTF8), None);
+        }
+
+        assert_eq!(unfit(&owne("Café"), &TextEncoding::ISO88591), Some("not ASCII"));
+    }
+
+    #[test]
+    fn reencode_frames() {
+        let mut all = All {
+            file: String::new(),
+            head: Some(ViewHead {
+                version: "4".to_string(),
+                flags: None,
+            }),
+            frames: Some([text("TIT2", "Title"), text("TALB", "김광석"), owne("Shop")]
+                .iter()
+                .cloned()
+                .map(|body| {
+                    ViewFrame {
+                        flags: None,
+                        body,
+                        picture: None,
+                        resource: None,
+                        genre: None,
+                    }
+                })
+                .collect()),
+            frame1: None,
+            audio_md5: None,
+        };
+
+        let (changed, report) = reencode_all(&mut all, &TextEncoding::ISO88591);
+        assert!(changed);
+        assert_eq!(report,
+                   vec!["TIT2: UTF8 -> ISO88591", "kept TALB UTF8: not in ISO-8859-1"]);
+
+        let (changed, report) = reencode_all(&mut all, &TextEncoding::UTF16LE);
+        assert!(changed);
+        assert_eq!(report,
+                   vec!["TIT2: ISO88591 -> UTF16LE",
+                        "TALB: UTF8 -> UTF16LE",
+                        "kept OWNE ISO88591: UTF-16 can not be written for COMR, OWNE and SYLT"]);
+
+        let encodings: Vec<_> = all.frames
+            .unwrap()
+            .iter()
+            .filter_map(|vf| tag::encoding(&vf.body).cloned())
+            .collect();
+        assert_eq!(encodings,
+                   vec![TextEncoding::UTF16LE, TextEncoding::UTF16LE, TextEncoding::ISO88591]);
+    }
+}
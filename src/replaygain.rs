@@ -0,0 +1,425 @@
+use artwork;
+use clap;
+use tag;
+
+use rtag::frame::{FrameBody, id};
+use symphonia;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use {All, ViewFrame, load, update};
+
+//
+// ReplayGain 2.0 is a gain to -18 LUFS of EBU R128.
+//
+const REFERENCE: f64 = -18.0;
+
+//
+// A biquad filter of the direct form 1.
+//
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Biquad {
+        Biquad {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1] -
+                self.a[1] * self.y[0] - self.a[2] * self.y[1];
+
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+//
+// The K-weighting of ITU BS.1770, a high shelf and a high pass for a sample rate.
+// coefficients are from libebur128.
+//
+fn k_weighting(rate: f64) -> (Biquad, Biquad) {
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+
+    let shelf = Biquad::new([(vh + vb * k / q + k * k) / a0,
+                             2.0 * (k * k - vh) / a0,
+                             (vh - vb * k / q + k * k) / a0],
+                            [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+
+    let high_pass = Biquad::new([1.0, -2.0, 1.0],
+                                [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+    (shelf, high_pass)
+}
+
+//
+// Energies of 400ms blocks that overlap by 75%, and a sample peak of a track.
+//
+#[derive(Default)]
+struct Loudness {
+    blocks: Vec<f64>,
+    peak: f64,
+}
+
+impl Loudness {
+    //
+    // EBU R128 integrated loudness with the absolute gate of -70 LUFS and the relative gate of -10 LU.
+    //
+    fn integrated(blocks: &[f64]) -> Option<f64> {
+        let lufs = |energy: f64| -0.691 + 10.0 * energy.log10();
+        let mean = |blocks: &Vec<f64>| blocks.iter().sum::<f64>() / blocks.len() as f64;
+
+        let absolute: Vec<f64> = blocks.iter().cloned().filter(|e| lufs(*e) > -70.0).collect();
+        if absolute.is_empty() {
+            return None;
+        }
+
+        let relative = lufs(mean(&absolute)) - 10.0;
+        let gated: Vec<f64> = absolute.into_iter().filter(|e| lufs(*e) > relative).collect();
+        if gated.is_empty() {
+            return None;
+        }
+
+        Some(lufs(mean(&gated)))
+    }
+}
+
+//
+// Decode a file and measure it.
+//
+fn analyze(file: &Path) -> Result<Loudness, Error> {
+    let mss = MediaSourceStream::new(Box::new(File::open(file)?), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("mp3");
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())?;
+    let mut format = probed.format;
+
+    let track = format.default_track().ok_or(Error::Unsupported("no track"))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut loudness = Loudness::default();
+    let mut filters: Vec<(Biquad, Biquad)> = Vec::new();
+    //
+    // energies of 100ms that make a 400ms block by 4.
+    //
+    let mut quarters: Vec<f64> = Vec::new();
+    let mut energy = 0.0;
+    let mut count = 0;
+    let mut quarter_len = 0;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            //
+            // the end of a stream. other errors are not to measure a part of a file.
+            //
+            Err(Error::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(e)) => {
+                debug!("skip a packet: {:?}, {}", file, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count();
+
+        if filters.is_empty() {
+            filters = vec![k_weighting(spec.rate as f64); channels];
+            quarter_len = spec.rate as usize / 10;
+        }
+
+        let buffer = buffer.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        if buffer.capacity() < decoded.capacity() * channels {
+            *buffer = SampleBuffer::new(decoded.capacity() as u64, spec);
+        }
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks(channels) {
+            for (sample, filter) in frame.iter().zip(filters.iter_mut()) {
+                let sample = *sample as f64;
+                loudness.peak = loudness.peak.max(sample.abs());
+
+                let weighted = filter.1.process(filter.0.process(sample));
+                energy += weighted * weighted;
+            }
+
+            count += 1;
+            if count == quarter_len {
+                quarters.push(energy / quarter_len as f64);
+                energy = 0.0;
+                count = 0;
+            }
+        }
+    }
+
+    loudness.blocks = quarters.windows(4).map(|w| w.iter().sum::<f64>() / 4.0).collect();
+    Ok(loudness)
+}
+
+//
+// "-6.53 dB"
+//
+fn gain(blocks: &[f64]) -> Option<String> {
+    Loudness::integrated(blocks).map(|lufs| format!("{:.2} dB", REFERENCE - lufs))
+}
+
+fn peak(peak: f64) -> String {
+    format!("{:.6}", peak)
+}
+
+//
+// (changed, report). TXXX of the same description is replaced.
+//
+fn set_all(all: &mut All, values: &[(&str, String)]) -> (bool, Vec<String>) {
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => 4,
+    };
+
+    let mut changed = false;
+    let mut report = Vec::new();
+    let frames = all.frames.get_or_insert_with(Vec::new);
+
+    for &(description, ref value) in values {
+        let old = frames.iter().position(|vf| match vf.body {
+            FrameBody::TXXX(ref frame) => frame.description.eq_ignore_ascii_case(description),
+            _ => false,
+        });
+
+        let same = old.and_then(|i| tag::text_of(&frames[i].body)).is_some_and(|(_, old)| old == *value);
+        if same {
+            continue;
+        }
+
+        let fbody = match tag::text_frame(&format!("TXXX:{}", description), value, version) {
+            Some(fbody) => fbody,
+            None => continue,
+        };
+
+        report.push(format!("{}: {}", description, value));
+        changed = true;
+
+        match old {
+            Some(i) => frames[i].body = fbody,
+            None => {
+                frames.push(ViewFrame {
+                    flags: None,
+                    body: fbody,
+                    picture: None,
+                    resource: None,
+                    genre: None,
+                })
+            }
+        }
+    }
+
+    (changed, report)
+}
+
+fn write(path: &Path, values: &[(&str, String)], dry_run: bool) {
+    let mut all = match load(path) {
+        Some(all) => all,
+        None => return,
+    };
+
+    let (changed, report) = set_all(&mut all, values);
+
+    if report.is_empty() {
+        debug!("replaygain is already tagged: {:?}", path);
+        return;
+    }
+
+    println!("{}", path.display());
+    for line in report {
+        println!("\t{}", line);
+    }
+
+    if dry_run || !changed {
+        return;
+    }
+
+    if all.head.is_none() {
+        all.head = Some(::ViewHead {
+            version: "4".to_string(),
+            flags: None,
+        });
+    }
+
+    if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+        error!("Can not write: {:?}, {:?}", path, e);
+    }
+}
+
+//
+// An album of a file. None for `track`.
+//
+fn album_of(path: &Path, by: &str) -> Option<String> {
+    match by {
+        "dir" => path.parent().map(|dir| dir.to_string_lossy().to_string()),
+        "album" => tag::texts(path).and_then(|texts| texts.get(id::TALB).cloned()),
+        _ => None,
+    }
+}
+
+pub fn replaygain(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+    let by = matches.value_of("replaygain").unwrap();
+
+    match by {
+        "track" | "dir" | "album" => (),
+        _ => {
+            error!("Unknown replaygain: {}. (track|dir|album)", by);
+            return;
+        }
+    }
+
+    //
+    // album => files with loudness. a file without an album is alone.
+    //
+    let mut albums: BTreeMap<Option<String>, Vec<(PathBuf, Loudness)>> = BTreeMap::new();
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        match analyze(&path) {
+            Ok(loudness) => albums.entry(album_of(&path, by)).or_default().push((path, loudness)),
+            Err(e) => error!("Can not decode: {:?}, {:?}", path, e),
+        }
+    }
+
+    for (album, tracks) in albums {
+        let album_values = match album {
+            Some(_) => {
+                let blocks: Vec<f64> = tracks.iter().flat_map(|t| t.1.blocks.iter().cloned()).collect();
+                let album_peak = tracks.iter().map(|t| t.1.peak).fold(0.0, f64::max);
+
+                gain(&blocks).map(|gain| {
+                    vec![("REPLAYGAIN_ALBUM_GAIN", gain), ("REPLAYGAIN_ALBUM_PEAK", peak(album_peak))]
+                })
+            }
+            None => None,
+        };
+
+        for (path, loudness) in tracks {
+            let mut values = match gain(&loudness.blocks) {
+                Some(gain) => {
+                    vec![("REPLAYGAIN_TRACK_GAIN", gain),
+                         ("REPLAYGAIN_TRACK_PEAK", peak(loudness.peak))]
+                }
+                None => {
+                    warn!("Too quiet to measure: {:?}", path);
+                    continue;
+                }
+            };
+
+            values.extend(album_values.iter().flatten().cloned());
+            write(&path, &values, dry_run);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // Energies of 400ms blocks of a sine of `amplitude` in one channel, like `analyze`.
+    //
+    fn sine_blocks(rate: f64, frequency: f64, amplitude: f64, seconds: usize) -> Vec<f64> {
+        let (mut shelf, mut high_pass) = k_weighting(rate);
+        let quarter_len = rate as usize / 10;
+
+        let quarters: Vec<f64> = (0..seconds * 10)
+            .map(|quarter| {
+                (0..quarter_len)
+                    .map(|i| {
+                        let t = (quarter * quarter_len + i) as f64 / rate;
+                        let sample = amplitude * (2.0 * PI * frequency * t).sin();
+                        let weighted = high_pass.process(shelf.process(sample));
+                        weighted * weighted
+                    })
+                    .sum::<f64>() / quarter_len as f64
+            })
+            .collect();
+
+        quarters.windows(4).map(|w| w.iter().sum::<f64>() / 4.0).collect()
+    }
+
+    #[test]
+    fn loudness_of_sine() {
+        //
+        // a full scale sine of 997 Hz in one channel is -3.01 LUFS. see ITU BS.1770
+        //
+        for &rate in &[44100.0, 48000.0] {
+            let lufs = Loudness::integrated(&sine_blocks(rate, 997.0, 1.0, 5)).unwrap();
+            assert!((lufs + 3.01).abs() < 0.05, "{} Hz: {}", rate, lufs);
+
+            let lufs = Loudness::integrated(&sine_blocks(rate, 997.0, 0.1, 5)).unwrap();
+            assert!((lufs + 23.01).abs() < 0.05, "{} Hz: {}", rate, lufs);
+        }
+
+        assert_eq!(gain(&sine_blocks(48000.0, 997.0, 0.1, 5)), Some("5.01 dB".to_string()));
+    }
+
+    #[test]
+    fn loudness_of_silence() {
+        assert_eq!(Loudness::integrated(&[]), None);
+        assert_eq!(Loudness::integrated(&sine_blocks(48000.0, 997.0, 0.0001, 2)), None);
+    }
+}
@@ -0,0 +1,426 @@
+use artwork;
+use base64;
+use hyper::Client;
+use hyper::Url;
+use md5;
+use regex::Regex;
+use rtag::frame::FrameBody;
+use rtag::rw::Readable;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+
+//
+// It split a `#{...}` placeholder from a value. (value without placeholder, placeholder)
+//
+pub fn extract_url(value: &str) -> Option<(String, String)> {
+    let re = Regex::new(r"#\{(.*)\}").unwrap();
+
+    re.captures(value).map(|c| {
+        let cap = c.get(1).map_or("", |m| m.as_str());
+        (re.replace(value, "").into_owned(), cap.to_string())
+    })
+}
+
+//
+// A placeholder can be one of
+// - http://, https:// url
+// - file: url
+// - data: uri
+// - absolute path, `~` path or path relative to `base`. `base` is the directory of a json file
+//
+pub fn to_bytes(cap_url: &str, base: &Path) -> Option<Vec<u8>> {
+    debug!("resource: {}", cap_url);
+
+    if cap_url.starts_with("data:") {
+        return data(cap_url);
+    }
+
+    if cap_url.starts_with("http:") || cap_url.starts_with("https:") ||
+       cap_url.starts_with("file:") {
+        let parsed_url = match Url::parse(cap_url) {
+            Ok(parsed_url) => parsed_url,
+            Err(_) => {
+                error!("Invalid url: {}", cap_url);
+                return None;
+            }
+        };
+
+        return if parsed_url.scheme() == "file" {
+            match parsed_url.to_file_path() {
+                Ok(path) => file(&path),
+                Err(e) => {
+                    error!("Invalid file path: {:?}", e);
+                    None
+                }
+            }
+        } else {
+            http(parsed_url)
+        };
+    }
+
+    file(&to_path(cap_url, base))
+}
+
+//
+// It fills a binary property of a frame from a placeholder.
+// the placeholder is taken from `resource` of a frame first, and then from a text property of it.
+//
+pub fn embed(frame_body: &mut FrameBody,
+             resource: &Option<String>,
+             shrink: &artwork::Shrink,
+             base: &Path) {
+    let is_picture = matches!(*frame_body, FrameBody::PIC(_) | FrameBody::APIC(_));
+
+    let (text, payload) = match payload(frame_body) {
+        Some(slots) => slots,
+        None => {
+            if resource.is_some() {
+                warn!("No binary property to load a resource: {:?}", resource);
+            }
+            return;
+        }
+    };
+
+    let cap_url = match *resource {
+        Some(ref resource) => {
+            match extract_url(resource) {
+                Some((_, extracted)) => extracted,
+                None => resource.to_string(),
+            }
+        }
+        None => {
+            match text {
+                Some(text) => {
+                    match extract_url(text) {
+                        Some((replaced, extracted)) => {
+                            *text = replaced;
+                            extracted
+                        }
+                        None => return,
+                    }
+                }
+                None => return,
+            }
+        }
+    };
+
+    let bytes = match to_bytes(cap_url.as_str(), base) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+
+    *payload = if is_picture {
+        match artwork::shrink(&bytes, shrink) {
+            Some(shrunk) => shrunk,
+            None => bytes,
+        }
+    } else {
+        bytes
+    };
+
+    if is_picture {
+        artwork::fix_format(frame_body);
+    }
+}
+
+//
+// Where a binary property goes on reading.
+//
+#[derive(Debug)]
+pub enum Dump {
+    Base64,
+    Dir(PathBuf),
+}
+
+//
+// It takes a binary property out of a frame, and returns a `resource` that loads it back.
+// files are named with md5 of the content, so the same artwork of an album is saved once.
+//
+pub fn dump(frame_body: &mut FrameBody, dump: &Dump) -> Option<String> {
+    let (_, payload) = payload(frame_body)?;
+
+    if payload.is_empty() {
+        return None;
+    }
+
+    let bytes = ::std::mem::take(payload);
+
+    match *dump {
+        Dump::Base64 => {
+            let mime_type = artwork::mime_type(&bytes).unwrap_or("application/octet-stream");
+            Some(format!("data:{};base64,{}", mime_type, base64::encode(&bytes)))
+        }
+        Dump::Dir(ref dir) => {
+            let ext = match artwork::image_format(&bytes) {
+                Some(format) => format.to_lowercase(),
+                None => "bin".to_string(),
+            };
+            let path = dir.join(format!("{:x}.{}", md5::compute(&bytes), ext));
+
+            if !path.exists() {
+                let saved = fs::create_dir_all(dir)
+                    .and_then(|_| File::create(&path))
+                    .and_then(|mut fs| fs.write_all(&bytes));
+
+                if let Err(e) = saved {
+                    error!("Can not save a binary: {:?}, {:?}", path, e);
+                    return None;
+                }
+            }
+
+            //
+            // a json is loaded from anywhere, so a path is not relative to the working directory.
+            //
+            match path.canonicalize() {
+                Ok(path) => path.to_str().map(|path| path.to_string()),
+                Err(e) => {
+                    error!("Can not resolve a binary: {:?}, {:?}", path, e);
+                    None
+                }
+            }
+        }
+    }
+}
+
+//
+// (text property that can have a placeholder, binary property)
+//
+fn payload(frame_body: &mut FrameBody) -> Option<(Option<&mut String>, &mut Vec<u8>)> {
+    match *frame_body {
+        FrameBody::PIC(ref mut f) => Some((Some(&mut f.description), &mut f.picture_data)),
+        FrameBody::APIC(ref mut f) => Some((Some(&mut f.description), &mut f.picture_data)),
+        FrameBody::GEOB(ref mut f) => {
+            Some((Some(&mut f.content_description), &mut f.encapsulation_object))
+        }
+        FrameBody::PRIV(ref mut f) => Some((Some(&mut f.owner_identifier), &mut f.private_data)),
+        FrameBody::UFID(ref mut f) => Some((Some(&mut f.owner_identifier), &mut f.identifier)),
+        FrameBody::AENC(ref mut f) => {
+            Some((Some(&mut f.owner_identifier), &mut f.encryption_info))
+        }
+        FrameBody::ENCR(ref mut f) => {
+            Some((Some(&mut f.owner_identifier), &mut f.encryption_data))
+        }
+        FrameBody::GRID(ref mut f) => {
+            Some((Some(&mut f.owner_identifier), &mut f.group_dependent_data))
+        }
+        FrameBody::COMR(ref mut f) => Some((Some(&mut f.description), &mut f.seller_logo)),
+        FrameBody::CRM(ref mut f) => Some((Some(&mut f.content), &mut f.encrypted_datablock)),
+        FrameBody::MCDI(ref mut f) => Some((None, &mut f.cd_toc)),
+        FrameBody::SIGN(ref mut f) => Some((None, &mut f.signature)),
+        FrameBody::SYTC(ref mut f) => Some((None, &mut f.tempo_data)),
+        FrameBody::POSS(ref mut f) => Some((None, &mut f.position)),
+        FrameBody::EQUA(ref mut f) => Some((None, &mut f.data)),
+        FrameBody::MLLT(ref mut f) => Some((None, &mut f.data)),
+        FrameBody::RVAD(ref mut f) => Some((None, &mut f.data)),
+        FrameBody::RVA2(ref mut f) => Some((None, &mut f.data)),
+        FrameBody::OBJECT(ref mut f) => Some((None, &mut f.data)),
+        _ => None,
+    }
+}
+
+pub fn to_path(cap_path: &str, base: &Path) -> PathBuf {
+    if cap_path == "~" || cap_path.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return Path::new(&home).join(cap_path.trim_start_matches('~').trim_start_matches('/'));
+        }
+    }
+
+    base.join(cap_path)
+}
+
+fn file(path: &Path) -> Option<Vec<u8>> {
+    debug!("file: {:?}", path);
+
+    let mut fs = match File::open(path) {
+        Ok(fs) => fs,
+        Err(e) => {
+            error!("Can not read a file: {:?}, {:?}", path, e);
+            return None;
+        }
+    };
+
+    match fs.all_bytes() {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            error!("Unknown error: {:?}", e);
+            None
+        }
+    }
+}
+
+fn http(url: Url) -> Option<Vec<u8>> {
+    debug!("http: '{}'", url);
+
+    let client = Client::new();
+    let mut response = match client.get(url).send() {
+        Ok(response) => response,
+        Err(e) => {
+            error!("Can not send http request: {:?}", e);
+            return None;
+        }
+    };
+
+    let mut buf = vec![0u8; 1024];
+    let mut dst = Vec::new();
+    while let Ok(read) = response.read(&mut buf) {
+        if read == 0 {
+            break;
+        }
+        dst.extend_from_slice(&buf[..read]);
+    }
+
+    Some(dst)
+}
+
+//
+// data:[<mediatype>][;base64],<data>
+//
+fn data(uri: &str) -> Option<Vec<u8>> {
+    let comma = match uri.find(',') {
+        Some(comma) => comma,
+        None => {
+            error!("Invalid data uri: {}", uri);
+            return None;
+        }
+    };
+
+    let (meta, payload) = uri.split_at(comma);
+    let payload = &payload[1..];
+
+    if meta.ends_with(";base64") {
+        let payload: String = payload.chars().filter(|ch| !ch.is_whitespace()).collect();
+        match base64::decode(&payload) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                error!("Invalid base64 data: {:?}", e);
+                None
+            }
+        }
+    } else {
+        Some(percent_decode(payload))
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                ret.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        ret.push(bytes[i]);
+        i += 1;
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat};
+    use rtag::frame::{APIC, GEOB};
+    use rtag::frame::types::{PictureType, TextEncoding};
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(3, 2).write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+        bytes
+    }
+
+    fn apic(picture_data: Vec<u8>) -> FrameBody {
+        FrameBody::APIC(APIC {
+            text_encoding: TextEncoding::ISO88591,
+            mime_type: "image/png".to_string(),
+            picture_type: PictureType::CoverFront,
+            description: "cover".to_string(),
+            picture_data,
+        })
+    }
+
+    fn geob(encapsulation_object: Vec<u8>) -> FrameBody {
+        FrameBody::GEOB(GEOB {
+            text_encoding: TextEncoding::ISO88591,
+            mime_type: "application/octet-stream".to_string(),
+            filename: "a.bin".to_string(),
+            content_description: "object".to_string(),
+            encapsulation_object,
+        })
+    }
+
+    //
+    // A frame is emptied by `dump`, and `embed` loads the same bytes back.
+    //
+    fn round_trip(original: FrameBody, dump_to: &Dump) -> String {
+        let mut fbody = original.clone();
+        let resource = dump(&mut fbody, dump_to).unwrap();
+        assert_ne!(fbody, original);
+
+        embed(&mut fbody, &Some(resource.clone()), &artwork::Shrink::default(), Path::new("/"));
+        assert_eq!(fbody, original);
+        resource
+    }
+
+    #[test]
+    fn dump_and_embed_base64() {
+        let resource = round_trip(apic(png()), &Dump::Base64);
+        assert!(resource.starts_with("data:image/png;base64,"));
+
+        let resource = round_trip(geob(vec![0, 1, 2, 0xff]), &Dump::Base64);
+        assert_eq!(resource, "data:application/octet-stream;base64,AAEC/w==");
+
+        let mut empty = geob(Vec::new());
+        assert_eq!(dump(&mut empty, &Dump::Base64), None);
+    }
+
+    #[test]
+    fn dump_and_embed_dir() {
+        let dir = env::temp_dir().join(format!("markdang-dump-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dump_to = Dump::Dir(dir.clone());
+
+        let bytes = png();
+        let resource = round_trip(apic(bytes.clone()), &dump_to);
+        let name = format!("{:x}.png", md5::compute(&bytes));
+        assert_eq!(resource, dir.canonicalize().unwrap().join(&name).to_str().unwrap());
+
+        //
+        // the same bytes are saved once.
+        //
+        assert_eq!(round_trip(apic(bytes.clone()), &dump_to), resource);
+
+        let object = b"object".to_vec();
+        let resource = round_trip(geob(object.clone()), &dump_to);
+        assert!(resource.ends_with(&format!("{:x}.bin", md5::compute(&object))));
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn data_uri() {
+        assert_eq!(data("data:,Hello%2C%20World"), Some(b"Hello, World".to_vec()));
+        assert_eq!(data("data:text/plain;base64,SGVs\nbG8="), Some(b"Hello".to_vec()));
+        assert_eq!(data("data:;base64,!!"), None);
+        assert_eq!(data("data:text/plain"), None);
+    }
+
+    #[test]
+    fn percent_decode_bytes() {
+        assert_eq!(percent_decode("a%41%e2%82%ac"), b"aA\xe2\x82\xac".to_vec());
+        assert_eq!(percent_decode("100%"), b"100%".to_vec());
+        assert_eq!(percent_decode("%4"), b"%4".to_vec());
+        assert_eq!(percent_decode("%zz"), b"%zz".to_vec());
+    }
+}
@@ -0,0 +1,232 @@
+use audio;
+use clap;
+use serde_json;
+use tag;
+
+use rtag::frame::framebody_to_id;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use load;
+
+//
+// Flags of the ID3v2 header byte by bit.
+//
+const HEAD_FLAGS: [(u8, &str); 4] = [(0x80, "Unsynchronisation"),
+                                     (0x40, "ExtendedHeader"),
+                                     (0x20, "Experimental"),
+                                     (0x10, "Footer")];
+
+#[derive(Debug, Default, Serialize)]
+struct Stats {
+    files: usize,
+    //
+    // "2.3", "2.4", "none"
+    //
+    versions: BTreeMap<String, usize>,
+    //
+    // frames by text encoding
+    //
+    encodings: BTreeMap<String, usize>,
+    //
+    // files by a frame id
+    //
+    frames: BTreeMap<String, usize>,
+    v1: usize,
+    v1_only: usize,
+    //
+    // files without an essential frame. see `tag::ESSENTIALS`
+    //
+    missing: BTreeMap<String, usize>,
+    head_flags: BTreeMap<String, usize>,
+    //
+    // files by a tag at the end. ex) APEv2
+    //
+    trailing_tags: BTreeMap<String, usize>,
+    tag_bytes: usize,
+    padding_bytes: usize,
+    audio_bytes: usize,
+}
+
+impl Stats {
+    fn add(&mut self, path: &Path) {
+        let all = match load(path) {
+            Some(all) => all,
+            None => return,
+        };
+
+        let bytes = match audio::read(path) {
+            Some(bytes) => bytes,
+            None => return,
+        };
+
+        self.files += 1;
+
+        let version = match all.head {
+            Some(ref head) => format!("2.{}", head.version),
+            None => "none".to_string(),
+        };
+        *self.versions.entry(version).or_insert(0) += 1;
+
+        let mut fbodies = Vec::new();
+        let mut ids = Vec::new();
+
+        for vf in all.frames.iter().flatten() {
+            if let Some(encoding) = tag::encoding(&vf.body) {
+                *self.encodings.entry(format!("{:?}", encoding)).or_insert(0) += 1;
+            }
+
+            let id = framebody_to_id(&vf.body, 4);
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+            fbodies.push(&vf.body);
+        }
+
+        for id in ids {
+            *self.frames.entry(id.to_string()).or_insert(0) += 1;
+        }
+
+        if all.frame1.is_some() {
+            self.v1 += 1;
+            if all.head.is_none() {
+                self.v1_only += 1;
+            }
+        }
+
+        for id in tag::missing_essentials(&fbodies) {
+            *self.missing.entry(id.to_string()).or_insert(0) += 1;
+        }
+
+        let layout = audio::layout(&bytes);
+
+        if let Some((_, flags)) = layout.id3v2 {
+            for &(bit, name) in HEAD_FLAGS.iter() {
+                if flags & bit != 0 {
+                    *self.head_flags.entry(name.to_string()).or_insert(0) += 1;
+                }
+            }
+        }
+
+        for &(name, _, len) in &layout.trailing {
+            *self.trailing_tags.entry(name.to_string()).or_insert(0) += 1;
+            self.tag_bytes += len;
+        }
+
+        self.tag_bytes += layout.id3v2_len;
+        self.padding_bytes += layout.padding;
+        self.audio_bytes += layout.audio_len;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let section = |f: &mut fmt::Formatter, name: &str, counts: &BTreeMap<String, usize>| {
+            writeln!(f, "{}", name)?;
+            for (key, count) in counts {
+                writeln!(f, "\t{}: {}", key, count)?;
+            }
+            Ok(())
+        };
+
+        writeln!(f, "files: {}", self.files)?;
+        section(f, "versions", &self.versions)?;
+        section(f, "encodings", &self.encodings)?;
+        section(f, "frames", &self.frames)?;
+        writeln!(f, "v1: {}", self.v1)?;
+        writeln!(f, "v1 only: {}", self.v1_only)?;
+        section(f, "missing", &self.missing)?;
+        section(f, "head flags", &self.head_flags)?;
+        section(f, "trailing tags", &self.trailing_tags)?;
+
+        let percent = |bytes: usize| {
+            100.0 * bytes as f64 / (self.tag_bytes + self.audio_bytes).max(1) as f64
+        };
+
+        writeln!(f, "tag bytes: {} ({:.2}%)", self.tag_bytes, percent(self.tag_bytes))?;
+        writeln!(f, "padding bytes: {} ({:.2}%)", self.padding_bytes, percent(self.padding_bytes))?;
+        writeln!(f, "audio bytes: {}", self.audio_bytes)
+    }
+}
+
+pub fn stats(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let mut stats = Stats::default();
+
+    for file in files {
+        match PathBuf::from(file).canonicalize() {
+            Ok(path) => stats.add(&path),
+            Err(e) => error!("{}: {:?}", file, e),
+        }
+    }
+
+    match matches.value_of("format") {
+        Some("j") | Some("jj") => {
+            match serde_json::to_string_pretty(&stats) {
+                Ok(json) => println!("{}", json),
+                Err(e) => error!("{:?}", e),
+            }
+        }
+        _ => print!("{}", stats),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tests::tagged;
+
+    fn stats_of(name: &str) -> Stats {
+        let path = tagged(name, 3, &[("TIT2", b"\0Title".to_vec())]);
+        let mut stats = Stats::default();
+        stats.add(&path);
+        stats.add(Path::new("/nonexistent.mp3"));
+        fs::remove_file(&path).unwrap();
+        stats
+    }
+
+    #[test]
+    fn text_of_stats() {
+        let stats = stats_of("stats-text");
+
+        assert_eq!(stats.to_string(),
+                   "files: 1\n\
+                    versions\n\
+                    \t2.3: 1\n\
+                    encodings\n\
+                    \tISO88591: 1\n\
+                    frames\n\
+                    \tTIT2: 1\n\
+                    v1: 0\n\
+                    v1 only: 0\n\
+                    missing\n\
+                    \tAPIC: 1\n\
+                    \tTALB: 1\n\
+                    \tTCON: 1\n\
+                    \tTDRC: 1\n\
+                    \tTPE1: 1\n\
+                    \tTRCK: 1\n\
+                    head flags\n\
+                    trailing tags\n\
+                    tag bytes: 26 (5.87%)\n\
+                    padding bytes: 0 (0.00%)\n\
+                    audio bytes: 417\n");
+    }
+
+    #[test]
+    fn json_of_stats() {
+        let stats = stats_of("stats-json");
+        let json = serde_json::to_value(&stats).unwrap();
+
+        assert_eq!(json["files"], 1);
+        assert_eq!(json["versions"]["2.3"], 1);
+        assert_eq!(json["frames"]["TIT2"], 1);
+        assert_eq!(json["v1_only"], 0);
+        assert_eq!(json["tag_bytes"], 26);
+        assert_eq!(json["audio_bytes"], 417);
+        assert!(json["head_flags"].as_object().unwrap().is_empty());
+    }
+}
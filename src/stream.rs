@@ -0,0 +1,372 @@
+use audio::{self, Header};
+use clap;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+//
+// A problem of MPEG frames. an offset is from the start of a file.
+//
+#[derive(Debug)]
+pub struct Problem {
+    pub kind: &'static str,
+    pub offset: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug)]
+pub struct Health {
+    pub frames: u32,
+    //
+    // frames with a CRC that is not checked.
+    //
+    pub unchecked: u32,
+    pub problems: Vec<Problem>,
+}
+
+impl Health {
+    //
+    // ok, bad, or none if there is no MPEG frame.
+    //
+    pub fn status(&self) -> &'static str {
+        if self.frames == 0 {
+            "none"
+        } else if self.problems.is_empty() {
+            "ok"
+        } else {
+            "bad"
+        }
+    }
+
+    //
+    // Kinds of problems in order of the first one. ex) "garbage,crc"
+    //
+    pub fn kinds(&self) -> Vec<&'static str> {
+        let mut kinds = Vec::new();
+        for problem in &self.problems {
+            if !kinds.contains(&problem.kind) {
+                kinds.push(problem.kind);
+            }
+        }
+        kinds
+    }
+}
+
+//
+// CRC-16 of MPEG audio. polynomial 0x8005, initial 0xffff.
+//
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for byte in bytes {
+        for i in (0..8).rev() {
+            let bit = (byte >> i) & 0x01 == 1;
+            let high = crc & 0x8000 != 0;
+            crc <<= 1;
+            if bit != high {
+                crc ^= 0x8005;
+            }
+        }
+    }
+
+    crc
+}
+
+//
+// Bytes of side information that a CRC of layer III covers.
+//
+fn side_info_len(header: &Header) -> usize {
+    let mono = header.channel_mode == 3;
+
+    match (header.version == 1, mono) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    }
+}
+
+//
+// Bytes after a CRC that it covers, with the 2nd and 3rd bytes of a header.
+// layer I covers bit allocation, and layer III covers side information.
+// layer II also covers scale factor selection that needs allocation tables, so it is None.
+//
+fn crc_len(header: &Header, frame: &[u8]) -> Option<usize> {
+    match header.layer {
+        1 if header.channel_mode == 3 => Some(16),
+        1 => {
+            //
+            // subbands from a bound of joint stereo have one allocation for both channels.
+            //
+            let bound = if header.channel_mode == 1 {
+                4 * ((frame[3] >> 4 & 0x03) as usize + 1)
+            } else {
+                32
+            };
+            Some((32 + bound) / 2)
+        }
+        3 => Some(side_info_len(header)),
+        _ => None,
+    }
+}
+
+//
+// Some(true) if a CRC is right or there is no CRC, and None if it can not be checked.
+//
+fn crc_ok(header: &Header, frame: &[u8]) -> Option<bool> {
+    if !header.crc {
+        return Some(true);
+    }
+
+    let covered_len = crc_len(header, frame)?;
+    if frame.len() < 6 + covered_len {
+        return Some(true);
+    }
+
+    let mut covered = vec![frame[2], frame[3]];
+    covered.extend_from_slice(&frame[6..6 + covered_len]);
+
+    Some(crc16(&covered) == u16::from_be_bytes([frame[4], frame[5]]))
+}
+
+//
+// Walk all frames after tags.
+//
+pub fn check(bytes: &[u8]) -> Health {
+    let (start, end) = audio::payload(bytes);
+    let audio = &bytes[start..end];
+
+    let mut health = Health {
+        frames: 0,
+        unchecked: 0,
+        problems: Vec::new(),
+    };
+
+    let mut at = match audio::sync(audio, 0) {
+        Some(at) => at,
+        None => return health,
+    };
+
+    if at > 0 {
+        health.problems.push(Problem {
+            kind: "garbage",
+            offset: start,
+            bytes: at,
+        });
+    }
+
+    let first = Header::parse(&audio[at..]).unwrap();
+
+    while at < audio.len() {
+        let header = match Header::parse(&audio[at..]) {
+            Some(ref header) if header.is_same_stream(&first) => header.clone(),
+            parsed => {
+                //
+                // a header of another stream is reported once and skipped as garbage.
+                //
+                if parsed.is_some() {
+                    health.problems.push(Problem {
+                        kind: "inconsistent",
+                        offset: start + at,
+                        bytes: 4,
+                    });
+                }
+
+                let next = audio::sync(audio, at + 1).unwrap_or(audio.len());
+                health.problems.push(Problem {
+                    kind: "garbage",
+                    offset: start + at,
+                    bytes: next - at,
+                });
+                at = next;
+                continue;
+            }
+        };
+
+        let len = header.frame_len();
+
+        if at + len > audio.len() {
+            health.problems.push(Problem {
+                kind: "truncated",
+                offset: start + at,
+                bytes: audio.len() - at,
+            });
+            break;
+        }
+
+        match crc_ok(&header, &audio[at..at + len]) {
+            Some(true) => (),
+            Some(false) => {
+                health.problems.push(Problem {
+                    kind: "crc",
+                    offset: start + at,
+                    bytes: len,
+                })
+            }
+            None => health.unchecked += 1,
+        }
+
+        health.frames += 1;
+        at += len;
+    }
+
+    health
+}
+
+//
+// Properties of `MPEG` for `--match`. ex) -m "MPEG.health='bad'"
+//
+pub fn to_map<'a>(file: &Path) -> HashMap<&'a str, String> {
+    let mut map = HashMap::new();
+
+    if let Some(bytes) = audio::read(file) {
+        let health = check(&bytes);
+        map.insert("health", health.status().to_string());
+        map.insert("frames", health.frames.to_string());
+        map.insert("problems", health.kinds().join(","));
+    }
+
+    map
+}
+
+pub fn check_files(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let bytes = match audio::read(&path) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+
+        let health = check(&bytes);
+
+        println!("{}: {} ({} frames)", path.display(), health.status(), health.frames);
+
+        //
+        // kind => (count, bytes, the first offset)
+        //
+        let mut summary: BTreeMap<&str, (usize, usize, usize)> = BTreeMap::new();
+        for problem in &health.problems {
+            let entry = summary.entry(problem.kind).or_insert((0, 0, problem.offset));
+            entry.0 += 1;
+            entry.1 += problem.bytes;
+        }
+
+        for (kind, (count, bytes, offset)) in summary {
+            println!("\t{}: {} times, {} bytes, first at 0x{:x}", kind, count, bytes, offset);
+        }
+
+        if health.unchecked > 0 {
+            println!("\tCRC not checked: {} frames", health.unchecked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //
+    // A frame of `header` with a right CRC of `covered` bytes. others are zeros.
+    //
+    fn frame(header: [u8; 4], covered: usize) -> Vec<u8> {
+        let len = Header::parse(&header).unwrap().frame_len();
+        let mut frame = header.to_vec();
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend((0..covered).map(|i| (i * 37 + 11) as u8));
+        frame.resize(len, 0);
+
+        let mut bytes = vec![frame[2], frame[3]];
+        bytes.extend_from_slice(&frame[6..6 + covered]);
+        let crc = crc16(&bytes).to_be_bytes();
+        frame[4] = crc[0];
+        frame[5] = crc[1];
+        frame
+    }
+
+    fn header(bytes: [u8; 4]) -> Header {
+        Header::parse(&bytes).unwrap()
+    }
+
+    #[test]
+    fn crc16_of_bytes() {
+        assert_eq!(crc16(b"123456789"), 0xaee7);
+        assert_eq!(crc16(&[]), 0xffff);
+    }
+
+    #[test]
+    fn side_info() {
+        assert_eq!(side_info_len(&header([0xff, 0xfa, 0x90, 0x00])), 32);
+        assert_eq!(side_info_len(&header([0xff, 0xfa, 0x90, 0xc0])), 17);
+        assert_eq!(side_info_len(&header([0xff, 0xf2, 0x90, 0x40])), 17);
+        assert_eq!(side_info_len(&header([0xff, 0xf2, 0x90, 0xc0])), 9);
+        assert_eq!(side_info_len(&header([0xff, 0xe2, 0x90, 0x00])), 17);
+        assert_eq!(side_info_len(&header([0xff, 0xe2, 0x90, 0xc0])), 9);
+    }
+
+    #[test]
+    fn covered_len() {
+        let frame = [0; 64];
+        assert_eq!(crc_len(&header([0xff, 0xfe, 0x90, 0xc0]), &frame), Some(16));
+        assert_eq!(crc_len(&header([0xff, 0xfe, 0x90, 0x00]), &frame), Some(32));
+        assert_eq!(crc_len(&header([0xff, 0xfe, 0x90, 0x50]), &[0xff, 0xfe, 0x90, 0x50]), Some(20));
+        assert_eq!(crc_len(&header([0xff, 0xfa, 0x90, 0x00]), &frame), Some(32));
+        assert_eq!(crc_len(&header([0xff, 0xfc, 0x90, 0x00]), &frame), None);
+    }
+
+    #[test]
+    fn crc_of_frame() {
+        for &(bytes, covered) in &[([0xff, 0xfa, 0x90, 0x00], 32),
+                                   ([0xff, 0xf2, 0x90, 0xc0], 9),
+                                   ([0xff, 0xfe, 0x90, 0x50], 20)] {
+            let good = frame(bytes, covered);
+            let header = header(bytes);
+            assert_eq!(crc_ok(&header, &good), Some(true));
+
+            let mut bad = good.clone();
+            bad[6 + covered - 1] ^= 0x01;
+            assert_eq!(crc_ok(&header, &bad), Some(false));
+
+            let mut after = good.clone();
+            after[6 + covered] ^= 0x01;
+            assert_eq!(crc_ok(&header, &after), Some(true));
+        }
+
+        let layer2 = frame([0xff, 0xfc, 0x90, 0x00], 0);
+        assert_eq!(crc_ok(&header([0xff, 0xfc, 0x90, 0x00]), &layer2), None);
+
+        let no_crc = [0xff, 0xfb, 0x90, 0x00, 0x12, 0x34];
+        assert_eq!(crc_ok(&header([0xff, 0xfb, 0x90, 0x00]), &no_crc), Some(true));
+    }
+
+    #[test]
+    fn health_of_frames() {
+        let good = frame([0xff, 0xfa, 0x90, 0x00], 32);
+        let mut bad = good.clone();
+        bad[10] ^= 0x80;
+
+        let mut bytes = b"junk".to_vec();
+        bytes.extend_from_slice(&good);
+        bytes.extend_from_slice(&bad);
+        bytes.extend_from_slice(&good);
+
+        let health = check(&bytes);
+        assert_eq!(health.frames, 3);
+        assert_eq!(health.unchecked, 0);
+        assert_eq!(health.status(), "bad");
+        assert_eq!(health.kinds(), vec!["garbage", "crc"]);
+        assert_eq!(health.problems[1].offset, 4 + good.len());
+
+        let layer2 = frame([0xff, 0xfc, 0x90, 0x00], 0);
+        let health = check(&[layer2.clone(), layer2].concat());
+        assert_eq!((health.frames, health.unchecked, health.status()), (2, 2, "ok"));
+    }
+}
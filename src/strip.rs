@@ -0,0 +1,327 @@
+use artwork;
+use clap;
+
+use regex::Regex;
+use rtag::frame::{FrameBody, framebody_to_id};
+
+use std::path::{Path, PathBuf};
+
+use {All, load, match_expr, match_ids, simple, update};
+
+//
+// A pattern of frames. ex) PRIV, W*, TXXX:Encoded by, COMM:eng, COMM::iTun*
+// `*` is any text, and an empty part is anything.
+//
+#[derive(Debug)]
+struct Pattern {
+    id: Regex,
+    //
+    // a language of COMM
+    //
+    language: Option<Regex>,
+    //
+    // a description of TXXX, WXXX and COMM
+    //
+    description: Option<Regex>,
+}
+
+//
+// "iTun*" => (?i)^iTun.*$
+//
+fn glob(value: &str) -> Option<Regex> {
+    if value.is_empty() {
+        return None;
+    }
+
+    let pattern = value.split('*').map(::regex::escape).collect::<Vec<_>>().join(".*");
+    Regex::new(&format!("(?i)^{}$", pattern)).ok()
+}
+
+impl Pattern {
+    fn parse(value: &str) -> Option<Pattern> {
+        let mut parts = value.trim().splitn(3, ':');
+        let id = glob(parts.next().unwrap_or(""))?;
+
+        let (language, description) = match (parts.next(), parts.next()) {
+            (Some(language), Some(description)) => (glob(language), glob(description)),
+            (Some(part), None) if value.trim().to_uppercase().starts_with("COMM") => (glob(part), None),
+            (Some(description), None) => (None, glob(description)),
+            _ => (None, None),
+        };
+
+        Some(Pattern {
+            id,
+            language,
+            description,
+        })
+    }
+
+    fn is_match(&self, fbody: &FrameBody, version: u8) -> bool {
+        let id_match = self.id.is_match(framebody_to_id(fbody, 4)) ||
+                       self.id.is_match(framebody_to_id(fbody, version));
+        if !id_match {
+            return false;
+        }
+
+        let (language, description) = match *fbody {
+            FrameBody::COMM(ref frame) => (Some(&frame.language), Some(&frame.short_description)),
+            FrameBody::TXXX(ref frame) => (None, Some(&frame.description)),
+            FrameBody::WXXX(ref frame) => (None, Some(&frame.description)),
+            _ => (None, None),
+        };
+
+        let part_match = |pattern: &Option<Regex>, value: Option<&String>| match *pattern {
+            Some(ref pattern) => value.is_some_and(|value| pattern.is_match(value)),
+            None => true,
+        };
+
+        part_match(&self.language, language) && part_match(&self.description, description)
+    }
+}
+
+//
+// PRIV, TXXX:description, COMM:language:description
+//
+fn describe(fbody: &FrameBody) -> String {
+    let id = framebody_to_id(fbody, 4);
+
+    match *fbody {
+        FrameBody::COMM(ref frame) => {
+            format!("{}:{}:{}", id, frame.language, frame.short_description)
+        }
+        FrameBody::TXXX(ref frame) => format!("{}:{}", id, frame.description),
+        FrameBody::WXXX(ref frame) => format!("{}:{}", id, frame.description),
+        _ => id.to_string(),
+    }
+}
+
+//
+// Descriptions of frames that are removed.
+//
+fn strip_all(all: &mut All, patterns: &[Pattern]) -> Vec<String> {
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => 4,
+    };
+
+    let mut report = Vec::new();
+
+    if let Some(ref mut frames) = all.frames {
+        frames.retain(|vf| {
+            if patterns.iter().any(|pattern| pattern.is_match(&vf.body, version)) {
+                report.push(describe(&vf.body));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    report
+}
+
+pub fn strip(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+    let value = matches.value_of("strip").unwrap();
+
+    let mut patterns = Vec::new();
+    for part in value.split(',').filter(|part| !part.trim().is_empty()) {
+        match Pattern::parse(part) {
+            Some(pattern) => patterns.push(pattern),
+            None => {
+                error!("Invalid pattern: {}", part);
+                return;
+            }
+        }
+    }
+
+    let filter = matches.value_of("match").map(match_expr);
+    let ids = matches.value_of("match").map(match_ids).unwrap_or_default();
+    let stream = ids.iter().any(|id| id == "MPEG");
+    let picture = ids.iter().any(|id| id == "APIC" || id == "PIC");
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        if filter.as_ref().is_some_and(|filter| simple(&path, filter, stream, picture).is_none()) {
+            debug!("not matched: {:?}", path);
+            continue;
+        }
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let report = strip_all(&mut all, &patterns);
+
+        if report.is_empty() {
+            debug!("nothing to strip: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for line in report {
+            println!("\t- {}", line);
+        }
+
+        if dry_run {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtag::frame::{COMM, PRIV, TEXT, TXXX, WXXX};
+    use rtag::frame::types::TextEncoding;
+
+    fn text(value: &str) -> TEXT {
+        TEXT {
+            text_encoding: TextEncoding::ISO88591,
+            text: value.to_string(),
+        }
+    }
+
+    fn comm(language: &str, description: &str) -> FrameBody {
+        FrameBody::COMM(COMM {
+            text_encoding: TextEncoding::ISO88591,
+            language: language.to_string(),
+            short_description: description.to_string(),
+            actual_text: String::new(),
+        })
+    }
+
+    fn txxx(description: &str) -> FrameBody {
+        FrameBody::TXXX(TXXX {
+            text_encoding: TextEncoding::ISO88591,
+            description: description.to_string(),
+            value: String::new(),
+        })
+    }
+
+    fn matched(pattern: &str, fbody: &FrameBody, version: u8) -> bool {
+        Pattern::parse(pattern).unwrap().is_match(fbody, version)
+    }
+
+    #[test]
+    fn glob_to_regex() {
+        assert_eq!(glob("iTun*").unwrap().as_str(), "(?i)^iTun.*$");
+        assert_eq!(glob("a.b*c?").unwrap().as_str(), r"(?i)^a\.b.*c\?$");
+        assert!(glob("").is_none());
+
+        let regex = glob("W*").unwrap();
+        assert!(regex.is_match("WXXX"));
+        assert!(regex.is_match("woaf"));
+        assert!(!regex.is_match("TWXX"));
+    }
+
+    #[test]
+    fn parts_of_pattern() {
+        let pattern = Pattern::parse(" COMM::iTun* ").unwrap();
+        assert!(pattern.language.is_none());
+        assert_eq!(pattern.description.unwrap().as_str(), "(?i)^iTun.*$");
+
+        let pattern = Pattern::parse("COMM:eng").unwrap();
+        assert_eq!(pattern.language.unwrap().as_str(), "(?i)^eng$");
+        assert!(pattern.description.is_none());
+
+        let pattern = Pattern::parse("TXXX:Encoded by").unwrap();
+        assert!(pattern.language.is_none());
+        assert_eq!(pattern.description.unwrap().as_str(), "(?i)^Encoded by$");
+
+        assert!(Pattern::parse("").is_none());
+        assert!(Pattern::parse(":eng").is_none());
+    }
+
+    #[test]
+    fn match_of_pattern() {
+        let priv_ = FrameBody::PRIV(PRIV {
+            owner_identifier: "WM/MediaClassPrimaryID".to_string(),
+            private_data: vec![],
+        });
+        assert!(matched("PRIV", &priv_, 3));
+        assert!(!matched("PRI", &priv_, 3));
+
+        let wxxx = FrameBody::WXXX(WXXX {
+            text_encoding: TextEncoding::ISO88591,
+            description: "Home".to_string(),
+            url: String::new(),
+        });
+        assert!(matched("W*", &wxxx, 4));
+        assert!(matched("WXXX:home", &wxxx, 4));
+        assert!(!matched("WXXX:Shop", &wxxx, 4));
+
+        assert!(matched("TXXX:Encoded by", &txxx("encoded BY"), 4));
+        assert!(!matched("TXXX:Encoded by", &txxx("Encoder"), 4));
+
+        let itunes = comm("eng", "iTunNORM");
+        assert!(matched("COMM", &itunes, 3));
+        assert!(matched("COMM:eng", &itunes, 3));
+        assert!(matched("COMM::iTun*", &itunes, 3));
+        assert!(matched("COMM:*:itunnorm", &itunes, 3));
+        assert!(!matched("COMM:kor", &itunes, 3));
+        assert!(!matched("COMM::iTun*", &comm("eng", ""), 3));
+
+        //
+        // an id of the version of a tag also matches.
+        //
+        let title = FrameBody::TIT2(text("Title"));
+        assert!(matched("TT2", &title, 2));
+        assert!(!matched("TT2", &title, 3));
+        assert!(!matched("TIT2:Title", &title, 4));
+    }
+
+    #[test]
+    fn strip_frames() {
+        let mut all = All {
+            file: String::new(),
+            head: None,
+            frames: Some([FrameBody::TIT2(text("Title")),
+                          comm("eng", "iTunNORM"),
+                          comm("eng", ""),
+                          txxx("Encoded by"),
+                          txxx("MusicBrainz Album Id")]
+                .iter()
+                .cloned()
+                .map(|body| {
+                    ::ViewFrame {
+                        flags: None,
+                        body,
+                        picture: None,
+                        resource: None,
+                        genre: None,
+                    }
+                })
+                .collect()),
+            frame1: None,
+            audio_md5: None,
+        };
+
+        let patterns: Vec<Pattern> = ["COMM::iTun*", "TXXX:Encoded by"]
+            .iter()
+            .filter_map(|p| Pattern::parse(p))
+            .collect();
+
+        assert_eq!(strip_all(&mut all, &patterns),
+                   vec!["COMM:eng:iTunNORM", "TXXX:Encoded by"]);
+        assert_eq!(all.frames
+                       .unwrap()
+                       .iter()
+                       .map(|vf| describe(&vf.body))
+                       .collect::<Vec<_>>(),
+                   vec!["TIT2", "COMM:eng:", "TXXX:MusicBrainz Album Id"]);
+    }
+}
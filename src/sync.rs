@@ -0,0 +1,315 @@
+use artwork;
+use clap;
+use genre;
+use tag;
+
+use rtag::frame::{Frame1, id};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use {All, fill, load, update};
+
+//
+// Text values of version 2 frames only. unlike `tag::texts`, frame1 is not used.
+//
+fn v2_texts(all: &All) -> HashMap<String, String> {
+    let mut ret = HashMap::new();
+
+    if let Some(ref frames) = all.frames {
+        for vf in frames {
+            if let Some((id, text)) = tag::text_of(&vf.body) {
+                if !text.trim().is_empty() {
+                    ret.entry(id).or_insert(text);
+                }
+            }
+        }
+    }
+
+    if !ret.contains_key(id::TDRC) {
+        if let Some(year) = ret.get(id::TYER).cloned() {
+            ret.insert(id::TDRC.to_string(), year);
+        }
+    }
+
+    ret
+}
+
+//
+// A value in ISO-8859-1 of `len` bytes. others are replaced with `?`.
+// (value, lost) lost is true if the value is truncated or replaced.
+//
+fn fit(value: &str, len: usize) -> (String, bool) {
+    let value = value.trim();
+    let fitted: String = value.chars()
+        .map(|ch| if (ch as u32) < 0x100 { ch } else { '?' })
+        .take(len)
+        .collect();
+    let lost = fitted != value;
+
+    (fitted, lost)
+}
+
+//
+// "3/12" => "3"
+//
+fn track_number(value: &str) -> Option<u8> {
+    let digits: String = value.trim()
+        .chars()
+        .take_while(|ch| ch.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+fn empty_frame1() -> Frame1 {
+    Frame1 {
+        title: String::new(),
+        artist: String::new(),
+        album: String::new(),
+        year: String::new(),
+        comment: String::new(),
+        track: String::new(),
+        genre: genre::NONE.to_string(),
+    }
+}
+
+//
+// (frame1, report of lossy fields). a field without a version 2 frame is kept from `old`.
+//
+fn to_frame1(texts: &HashMap<String, String>, old: &Option<Frame1>) -> (Frame1, Vec<String>) {
+    let mut frame1 = match *old {
+        Some(ref frame1) => frame1.clone(),
+        None => empty_frame1(),
+    };
+    let mut lost = Vec::new();
+
+    {
+        let mut text = |id: &str, len: usize, field: &mut String| {
+            if let Some(value) = texts.get(id) {
+                let (fitted, truncated) = fit(value, len);
+                if truncated {
+                    lost.push(format!("{}: \"{}\" => \"{}\"", id, value, fitted));
+                }
+                *field = fitted;
+            }
+        };
+
+        text(id::TIT2, 30, &mut frame1.title);
+        text(id::TPE1, 30, &mut frame1.artist);
+        text(id::TALB, 30, &mut frame1.album);
+        //
+        // frame1 of rtag is always version 1.1 that has a track, so a comment is 28 bytes.
+        //
+        text(id::COMM, 28, &mut frame1.comment);
+    }
+
+    if let Some(year) = texts.get(id::TDRC) {
+        frame1.year = year.trim().chars().take(4).collect();
+    }
+
+    if let Some(track) = texts.get(id::TRCK) {
+        match track_number(track) {
+            Some(number) => frame1.track = number.to_string(),
+            None => {
+                lost.push(format!("{}: \"{}\" => \"\"", id::TRCK, track));
+                frame1.track = String::new();
+            }
+        }
+    }
+
+    if let Some(tcon) = texts.get(id::TCON) {
+        match genre::names(tcon).first().and_then(|name| genre::index(name)) {
+            Some(index) => frame1.genre = index.to_string(),
+            None => {
+                lost.push(format!("{}: \"{}\" => \"\"", id::TCON, tcon));
+                frame1.genre = genre::NONE.to_string();
+            }
+        }
+    }
+
+    (frame1, lost)
+}
+
+//
+// (changed, report)
+//
+fn to_v1(all: &mut All) -> (bool, Vec<String>) {
+    let (frame1, lost) = to_frame1(&v2_texts(all), &all.frame1);
+
+    let mut report = Vec::new();
+
+    let old = all.frame1.clone().unwrap_or_else(empty_frame1);
+    let changed = old != frame1;
+
+    if changed {
+        report.push(format!("frame1: {:?}", frame1));
+        all.frame1 = Some(frame1);
+    }
+
+    report.extend(lost.into_iter().map(|lost| format!("lossy {}", lost)));
+    (changed, report)
+}
+
+fn to_v2(all: &mut All) -> (bool, Vec<String>) {
+    let frame1 = match all.frame1.take() {
+        Some(frame1) => frame1,
+        None => return (false, Vec::new()),
+    };
+
+    let version = match all.head {
+        Some(ref head) => head.version.parse().unwrap_or(4),
+        None => 4,
+    };
+
+    let mut report = Vec::new();
+
+    for (id, value) in tag::frame1_texts(&frame1) {
+        if !value.is_empty() && fill(all, id, &value, version) {
+            report.push(format!("{}: {}", id, value));
+        }
+    }
+
+    (!report.is_empty(), report)
+}
+
+pub fn sync(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+    let to = matches.value_of("sync").unwrap();
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let mut all = match load(&path) {
+            Some(all) => all,
+            None => continue,
+        };
+
+        let (changed, report) = match to {
+            "v1" => to_v1(&mut all),
+            "v2" => to_v2(&mut all),
+            _ => {
+                error!("Unknown sync: {}. (v1|v2)", to);
+                return;
+            }
+        };
+
+        if report.is_empty() {
+            debug!("nothing to sync: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for line in report {
+            println!("\t{}", line);
+        }
+
+        if dry_run || !changed {
+            continue;
+        }
+
+        if let Err(e) = update(&mut all, &artwork::Shrink::default(), Path::new("")) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ViewHead;
+
+    fn texts(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(id, value)| (id.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn fit_to_bytes() {
+        assert_eq!(fit(" Title ", 30), ("Title".to_string(), false));
+        assert_eq!(fit(&"a".repeat(31), 30), ("a".repeat(30), true));
+        assert_eq!(fit("Café", 30), ("Café".to_string(), false));
+        assert_eq!(fit("김광석", 30), ("???".to_string(), true));
+    }
+
+    #[test]
+    fn number_of_track() {
+        assert_eq!(track_number("3/12"), Some(3));
+        assert_eq!(track_number(" 07 "), Some(7));
+        assert_eq!(track_number("256"), None);
+        assert_eq!(track_number("/12"), None);
+        assert_eq!(track_number("A1"), None);
+    }
+
+    #[test]
+    fn frame1_of_texts() {
+        let long = "b".repeat(40);
+        let (frame1, lost) = to_frame1(&texts(&[(id::TIT2, "Title"),
+                                                 (id::TALB, &long),
+                                                 (id::COMM, &"c".repeat(29)),
+                                                 (id::TDRC, "1985-05-13"),
+                                                 (id::TRCK, "4/9"),
+                                                 (id::TCON, "(17)")]),
+                                        &None);
+
+        assert_eq!(frame1.title, "Title");
+        assert_eq!(frame1.album, "b".repeat(30));
+        assert_eq!(frame1.comment, "c".repeat(28));
+        assert_eq!(frame1.year, "1985");
+        assert_eq!(frame1.track, "4");
+        assert_eq!(frame1.genre, "17");
+        assert_eq!(lost.len(), 2);
+        assert!(lost[0].starts_with("TALB: "));
+        assert!(lost[1].starts_with("COMM: "));
+
+        let mut old = empty_frame1();
+        old.artist = "Dio".to_string();
+        let (frame1, lost) = to_frame1(&texts(&[(id::TRCK, "A1"), (id::TCON, "Unknown Genre")]),
+                                       &Some(old));
+
+        assert_eq!(frame1.artist, "Dio");
+        assert_eq!(frame1.track, "");
+        assert_eq!(frame1.genre, genre::NONE.to_string());
+        assert_eq!(lost,
+                   vec!["TRCK: \"A1\" => \"\"".to_string(),
+                        "TCON: \"Unknown Genre\" => \"\"".to_string()]);
+    }
+
+    #[test]
+    fn frame1_to_v2() {
+        let mut frame1 = empty_frame1();
+        frame1.title = "Rock 'N' Roll Children".to_string();
+        frame1.track = "4".to_string();
+        frame1.genre = "17".to_string();
+
+        let mut all = All {
+            file: String::new(),
+            head: Some(ViewHead {
+                version: "3".to_string(),
+                flags: None,
+            }),
+            frames: Some(vec![]),
+            frame1: Some(frame1.clone()),
+            audio_md5: None,
+        };
+
+        let (changed, report) = to_v2(&mut all);
+        assert!(changed);
+        assert_eq!(report, vec!["TIT2: Rock 'N' Roll Children", "TRCK: 4", "TCON: Rock"]);
+
+        let texts = v2_texts(&all);
+        assert_eq!(texts[id::TCON], "Rock");
+
+        //
+        // the genre index comes back from the name
+        //
+        let (back, lost) = to_frame1(&texts, &None);
+        assert!(lost.is_empty());
+        assert_eq!(back, frame1);
+    }
+}
@@ -0,0 +1,608 @@
+use rtag::metadata::MetadataReader as Reader;
+use rtag::metadata::Unit;
+use rtag::frame::*;
+use rtag::frame::types::{self, TextEncoding};
+use serde_json;
+
+use encoding::{DecoderTrap, EncoderTrap, Encoding, EncodingRef};
+use encoding::all::{ISO_8859_1, UTF_16BE, UTF_16LE, UTF_8};
+
+use audio;
+use genre;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+//
+// Text values of a file by version 4 frame id. ex) TIT2 => "Title"
+// TXXX is keyed with its description. ex) TXXX:REPLAYGAIN_TRACK_GAIN
+// if a frame is not in version 2, a value of frame1 is used. TDRC falls back to TYER.
+//
+pub fn texts(file: &Path) -> Option<HashMap<String, String>> {
+    let reader = match Reader::new(file.to_str().unwrap()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            warn!("Can not read: {:?}, {:?}", file, e);
+            return None;
+        }
+    };
+
+    let mut ret = HashMap::new();
+    let mut frame1 = None;
+
+    for unit in reader {
+        match unit {
+            Unit::FrameV2(_, ref fbody) => {
+                if let Some((id, text)) = text_of(fbody) {
+                    ret.entry(id).or_insert(text);
+                }
+            }
+            Unit::FrameV1(frame) => frame1 = Some(frame),
+            _ => (),
+        }
+    }
+
+    //
+    // version 3 has TYER instead of TDRC.
+    //
+    if !ret.contains_key(id::TDRC) {
+        if let Some(year) = ret.get(id::TYER).cloned() {
+            ret.insert(id::TDRC.to_string(), year);
+        }
+    }
+
+    if let Some(tcon) = ret.get_mut(id::TCON) {
+        *tcon = genre::resolve(tcon);
+    }
+
+    if let Some(frame1) = frame1 {
+        for (id, value) in frame1_texts(&frame1) {
+            if !value.is_empty() && !ret.contains_key(id) {
+                ret.insert(id.to_string(), value);
+            }
+        }
+    }
+
+    Some(ret)
+}
+
+pub fn text_of(fbody: &FrameBody) -> Option<(String, String)> {
+    match *fbody {
+        FrameBody::TXXX(ref frame) => {
+            Some((format!("TXXX:{}", frame.description), frame.value.clone()))
+        }
+        FrameBody::COMM(ref frame) => Some(("COMM".to_string(), frame.actual_text.clone())),
+        _ => {
+            text_body(fbody).map(|frame| (framebody_to_id(fbody, 4).to_string(), frame.text.clone()))
+        }
+    }
+}
+
+pub fn text_body(fbody: &FrameBody) -> Option<&TEXT> {
+    match *fbody {
+        FrameBody::TALB(ref f) | FrameBody::TBPM(ref f) | FrameBody::TCOM(ref f) |
+        FrameBody::TCON(ref f) | FrameBody::TCOP(ref f) | FrameBody::TDAT(ref f) |
+        FrameBody::TDEN(ref f) | FrameBody::TDLY(ref f) | FrameBody::TDOR(ref f) |
+        FrameBody::TDRC(ref f) | FrameBody::TDRL(ref f) | FrameBody::TDTG(ref f) |
+        FrameBody::TENC(ref f) | FrameBody::TEXT(ref f) | FrameBody::TFLT(ref f) |
+        FrameBody::TIME(ref f) | FrameBody::TIPL(ref f) | FrameBody::TIT1(ref f) |
+        FrameBody::TIT2(ref f) | FrameBody::TIT3(ref f) | FrameBody::TKEY(ref f) |
+        FrameBody::TLAN(ref f) | FrameBody::TLEN(ref f) | FrameBody::TMCL(ref f) |
+        FrameBody::TMED(ref f) | FrameBody::TMOO(ref f) | FrameBody::TOAL(ref f) |
+        FrameBody::TOFN(ref f) | FrameBody::TOLY(ref f) | FrameBody::TOPE(ref f) |
+        FrameBody::TORY(ref f) | FrameBody::TOWN(ref f) | FrameBody::TPE1(ref f) |
+        FrameBody::TPE2(ref f) | FrameBody::TPE3(ref f) | FrameBody::TPE4(ref f) |
+        FrameBody::TPOS(ref f) | FrameBody::TPRO(ref f) | FrameBody::TPUB(ref f) |
+        FrameBody::TRCK(ref f) | FrameBody::TRDA(ref f) | FrameBody::TRSN(ref f) |
+        FrameBody::TRSO(ref f) | FrameBody::TSIZ(ref f) | FrameBody::TSOA(ref f) |
+        FrameBody::TSOP(ref f) | FrameBody::TSOT(ref f) | FrameBody::TSRC(ref f) |
+        FrameBody::TSSE(ref f) | FrameBody::TYER(ref f) | FrameBody::TSST(ref f) => Some(f),
+        _ => None,
+    }
+}
+
+fn text_body_mut(fbody: &mut FrameBody) -> Option<&mut TEXT> {
+    match *fbody {
+        FrameBody::TALB(ref mut f) | FrameBody::TBPM(ref mut f) | FrameBody::TCOM(ref mut f) |
+        FrameBody::TCON(ref mut f) | FrameBody::TCOP(ref mut f) | FrameBody::TDAT(ref mut f) |
+        FrameBody::TDEN(ref mut f) | FrameBody::TDLY(ref mut f) | FrameBody::TDOR(ref mut f) |
+        FrameBody::TDRC(ref mut f) | FrameBody::TDRL(ref mut f) | FrameBody::TDTG(ref mut f) |
+        FrameBody::TENC(ref mut f) | FrameBody::TEXT(ref mut f) | FrameBody::TFLT(ref mut f) |
+        FrameBody::TIME(ref mut f) | FrameBody::TIPL(ref mut f) | FrameBody::TIT1(ref mut f) |
+        FrameBody::TIT2(ref mut f) | FrameBody::TIT3(ref mut f) | FrameBody::TKEY(ref mut f) |
+        FrameBody::TLAN(ref mut f) | FrameBody::TLEN(ref mut f) | FrameBody::TMCL(ref mut f) |
+        FrameBody::TMED(ref mut f) | FrameBody::TMOO(ref mut f) | FrameBody::TOAL(ref mut f) |
+        FrameBody::TOFN(ref mut f) | FrameBody::TOLY(ref mut f) | FrameBody::TOPE(ref mut f) |
+        FrameBody::TORY(ref mut f) | FrameBody::TOWN(ref mut f) | FrameBody::TPE1(ref mut f) |
+        FrameBody::TPE2(ref mut f) | FrameBody::TPE3(ref mut f) | FrameBody::TPE4(ref mut f) |
+        FrameBody::TPOS(ref mut f) | FrameBody::TPRO(ref mut f) | FrameBody::TPUB(ref mut f) |
+        FrameBody::TRCK(ref mut f) | FrameBody::TRDA(ref mut f) | FrameBody::TRSN(ref mut f) |
+        FrameBody::TRSO(ref mut f) | FrameBody::TSIZ(ref mut f) | FrameBody::TSOA(ref mut f) |
+        FrameBody::TSOP(ref mut f) | FrameBody::TSOT(ref mut f) | FrameBody::TSRC(ref mut f) |
+        FrameBody::TSSE(ref mut f) | FrameBody::TYER(ref mut f) |
+        FrameBody::TSST(ref mut f) => Some(f),
+        _ => None,
+    }
+}
+
+//
+// (version 4 frame id, value) of frame1 fields. a genre is a name, empty if there is none.
+//
+pub fn frame1_texts(frame1: &Frame1) -> Vec<(&'static str, String)> {
+    vec![(id::TIT2, frame1.title.clone()),
+         (id::TPE1, frame1.artist.clone()),
+         (id::TALB, frame1.album.clone()),
+         (id::TDRC, frame1.year.clone()),
+         (id::COMM, frame1.comment.clone()),
+         (id::TRCK, frame1.track.clone()),
+         (id::TCON, genre::from_frame1(&frame1.genre).unwrap_or_default().to_string())]
+}
+
+//
+// A frame of a text value. `id` is a version 4 frame id or `TXXX:description` like `texts`.
+// text is encoded in UTF-8 for version 4, and UTF-16 for others.
+// TXXX and COMM of others are ISO-8859-1 if it can have the text.
+//
+pub fn text_frame(id: &str, text: &str, version: u8) -> Option<FrameBody> {
+    let text_encoding = if version == 4 {
+        TextEncoding::UTF8
+    } else if id.chars().chain(text.chars()).all(|ch| (ch as u32) < 0x100) {
+        TextEncoding::ISO88591
+    } else {
+        TextEncoding::UTF16LE
+    };
+
+    if let Some(description) = id.strip_prefix("TXXX:") {
+        return Some(FrameBody::TXXX(TXXX {
+            text_encoding,
+            description: description.to_string(),
+            value: text.to_string(),
+        }));
+    }
+
+    if id == id::COMM {
+        return Some(FrameBody::COMM(COMM {
+            text_encoding,
+            language: "eng".to_string(),
+            short_description: String::new(),
+            actual_text: text.to_string(),
+        }));
+    }
+
+    //
+    // version 2 and 3 have TYER instead of TDRC.
+    //
+    let (id, text) = if id == id::TDRC && version < 4 {
+        (id::TYER, text.chars().take(4).collect())
+    } else {
+        (id, text.to_string())
+    };
+
+    let mut bytes = Vec::new();
+    if version == 4 {
+        bytes.push(3);
+        bytes.extend_from_slice(text.as_bytes());
+    } else {
+        bytes.push(1);
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+    }
+
+    match read_framebody_with_id(id, 4, Cursor::new(bytes)) {
+        Ok(fbody) => {
+            if text_body(&fbody).is_some() && framebody_to_id(&fbody, 4) == id {
+                Some(fbody)
+            } else {
+                warn!("Not a text frame: {}", id);
+                None
+            }
+        }
+        Err(e) => {
+            warn!("Can not make a frame: {}, {:?}", id, e);
+            None
+        }
+    }
+}
+
+pub fn encoding_mut(fbody: &mut FrameBody) -> Option<&mut TextEncoding> {
+    match *fbody {
+        FrameBody::PIC(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::APIC(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::COMM(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::COMR(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::GEOB(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::IPLS(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::OWNE(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::SYLT(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::USER(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::USLT(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::TXXX(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::WXXX(ref mut f) => Some(&mut f.text_encoding),
+        FrameBody::TALB(ref mut f) | FrameBody::TBPM(ref mut f) | FrameBody::TCOM(ref mut f) |
+        FrameBody::TCON(ref mut f) | FrameBody::TCOP(ref mut f) | FrameBody::TDAT(ref mut f) |
+        FrameBody::TDEN(ref mut f) | FrameBody::TDLY(ref mut f) | FrameBody::TDOR(ref mut f) |
+        FrameBody::TDRC(ref mut f) | FrameBody::TDRL(ref mut f) | FrameBody::TDTG(ref mut f) |
+        FrameBody::TENC(ref mut f) | FrameBody::TEXT(ref mut f) | FrameBody::TFLT(ref mut f) |
+        FrameBody::TIME(ref mut f) | FrameBody::TIPL(ref mut f) | FrameBody::TIT1(ref mut f) |
+        FrameBody::TIT2(ref mut f) | FrameBody::TIT3(ref mut f) | FrameBody::TKEY(ref mut f) |
+        FrameBody::TLAN(ref mut f) | FrameBody::TLEN(ref mut f) | FrameBody::TMCL(ref mut f) |
+        FrameBody::TMED(ref mut f) | FrameBody::TMOO(ref mut f) | FrameBody::TOAL(ref mut f) |
+        FrameBody::TOFN(ref mut f) | FrameBody::TOLY(ref mut f) | FrameBody::TOPE(ref mut f) |
+        FrameBody::TORY(ref mut f) | FrameBody::TOWN(ref mut f) | FrameBody::TPE1(ref mut f) |
+        FrameBody::TPE2(ref mut f) | FrameBody::TPE3(ref mut f) | FrameBody::TPE4(ref mut f) |
+        FrameBody::TPOS(ref mut f) | FrameBody::TPRO(ref mut f) | FrameBody::TPUB(ref mut f) |
+        FrameBody::TRCK(ref mut f) | FrameBody::TRDA(ref mut f) | FrameBody::TRSN(ref mut f) |
+        FrameBody::TRSO(ref mut f) | FrameBody::TSIZ(ref mut f) | FrameBody::TSOA(ref mut f) |
+        FrameBody::TSOP(ref mut f) | FrameBody::TSOT(ref mut f) | FrameBody::TSRC(ref mut f) |
+        FrameBody::TSSE(ref mut f) | FrameBody::TYER(ref mut f) |
+        FrameBody::TSST(ref mut f) => Some(&mut f.text_encoding),
+        _ => None,
+    }
+}
+
+pub fn encoding(fbody: &FrameBody) -> Option<&TextEncoding> {
+    match *fbody {
+        FrameBody::PIC(ref f) => Some(&f.text_encoding),
+        FrameBody::APIC(ref f) => Some(&f.text_encoding),
+        FrameBody::COMM(ref f) => Some(&f.text_encoding),
+        FrameBody::COMR(ref f) => Some(&f.text_encoding),
+        FrameBody::GEOB(ref f) => Some(&f.text_encoding),
+        FrameBody::IPLS(ref f) => Some(&f.text_encoding),
+        FrameBody::OWNE(ref f) => Some(&f.text_encoding),
+        FrameBody::SYLT(ref f) => Some(&f.text_encoding),
+        FrameBody::USER(ref f) => Some(&f.text_encoding),
+        FrameBody::USLT(ref f) => Some(&f.text_encoding),
+        FrameBody::TXXX(ref f) => Some(&f.text_encoding),
+        FrameBody::WXXX(ref f) => Some(&f.text_encoding),
+        _ => text_body(fbody).map(|f| &f.text_encoding),
+    }
+}
+
+//
+// Text of bytes in an encoding byte of a frame. a BOM decides the byte order of UTF-16,
+// and is removed.
+//
+pub fn decode(encoding: u8, bytes: &[u8]) -> Option<String> {
+    let codec: EncodingRef = match encoding {
+        0 => ISO_8859_1,
+        1 if bytes.starts_with(&[0xfe, 0xff]) => UTF_16BE,
+        1 => UTF_16LE,
+        2 => UTF_16BE,
+        3 => UTF_8,
+        _ => return None,
+    };
+
+    codec.decode(bytes, DecoderTrap::Replace).ok().map(|text| text.replace('\u{feff}', ""))
+}
+
+//
+// (text, rest) of a string that ends with a null. a null of UTF-16 is two bytes at an even offset.
+//
+fn split_string(encoding: u8, bytes: &[u8]) -> (String, &[u8]) {
+    let (at, len) = if encoding == 1 || encoding == 2 {
+        (bytes.chunks(2).position(|c| c == [0, 0]).map(|i| i * 2), 2)
+    } else {
+        (bytes.iter().position(|b| *b == 0), 1)
+    };
+
+    match at {
+        Some(at) => (decode(encoding, &bytes[..at]).unwrap_or_default(), &bytes[at + len..]),
+        None => (decode(encoding, bytes).unwrap_or_default(), &[]),
+    }
+}
+
+//
+// A string at the end of a frame. a null after it is not a part of it.
+//
+fn last_string(encoding: u8, bytes: &[u8]) -> String {
+    decode(encoding, bytes).unwrap_or_default().trim_end_matches('\0').to_string()
+}
+
+//
+// rtag reads strings of frames other than text frames as UTF-8, and can not read UTF-16 of them.
+// it also trims text of text frames. they are decoded again from `data` of the frame.
+//
+pub fn decode_strings(fbody: &mut FrameBody, data: &[u8]) {
+    let (encoding, data) = match data.split_first() {
+        Some((&encoding, data)) => (encoding, data),
+        None => return,
+    };
+
+    match *fbody {
+        FrameBody::COMM(ref mut f) if data.len() >= 3 => {
+            let (description, text) = split_string(encoding, &data[3..]);
+            f.short_description = description;
+            f.actual_text = last_string(encoding, text);
+        }
+        FrameBody::USLT(ref mut f) if data.len() >= 3 => {
+            let (descriptor, lyrics) = split_string(encoding, &data[3..]);
+            f.content_descriptor = descriptor;
+            f.lyrics = last_string(encoding, lyrics);
+        }
+        FrameBody::USER(ref mut f) if data.len() >= 3 => {
+            f.actual_text = last_string(encoding, &data[3..]);
+        }
+        FrameBody::IPLS(ref mut f) => {
+            f.people_list_strings = last_string(encoding, data);
+        }
+        FrameBody::GEOB(ref mut f) => {
+            //
+            // a mime type is before a file name.
+            //
+            if let Some(at) = data.iter().position(|b| *b == 0) {
+                let (filename, rest) = split_string(encoding, &data[at + 1..]);
+                let (description, object) = split_string(encoding, rest);
+                f.filename = filename;
+                f.content_description = description;
+                f.encapsulation_object = object.to_vec();
+            }
+        }
+        FrameBody::TXXX(ref mut f) => {
+            let (description, value) = split_string(encoding, data);
+            f.description = description;
+            f.value = last_string(encoding, value);
+        }
+        FrameBody::WXXX(ref mut f) => {
+            f.description = split_string(encoding, data).0;
+        }
+        FrameBody::APIC(ref mut f) => {
+            //
+            // a mime type and a picture type are before a description.
+            //
+            if let Some(at) = data.iter().position(|b| *b == 0) {
+                if data.len() > at + 1 {
+                    let (description, picture) = split_string(encoding, &data[at + 2..]);
+                    f.description = description;
+                    f.picture_data = picture.to_vec();
+                }
+            }
+        }
+        FrameBody::PIC(ref mut f) if data.len() >= 4 => {
+            let (description, picture) = split_string(encoding, &data[4..]);
+            f.description = description;
+            f.picture_data = picture.to_vec();
+        }
+        _ => {
+            if let Some(f) = text_body_mut(fbody) {
+                f.text = last_string(encoding, data);
+            }
+        }
+    }
+}
+
+//
+// Bytes of a string in an encoding. UTF-16 has a BOM in little endian.
+//
+fn encode(encoding: &TextEncoding, text: &str, terminated: bool) -> Vec<u8> {
+    let mut bytes = match *encoding {
+        TextEncoding::ISO88591 => ISO_8859_1.encode(text, EncoderTrap::Replace).unwrap_or_default(),
+        TextEncoding::UTF16LE => {
+            let mut bytes = vec![0xff, 0xfe];
+            bytes.extend(UTF_16LE.encode(text, EncoderTrap::Replace).unwrap_or_default());
+            bytes
+        }
+        TextEncoding::UTF16BE => UTF_16BE.encode(text, EncoderTrap::Replace).unwrap_or_default(),
+        TextEncoding::UTF8 => text.as_bytes().to_vec(),
+    };
+
+    if terminated {
+        match *encoding {
+            TextEncoding::UTF16LE | TextEncoding::UTF16BE => bytes.extend_from_slice(&[0, 0]),
+            _ => bytes.push(0),
+        }
+    }
+
+    bytes
+}
+
+//
+// "eng" => 3 bytes of a language or an image format.
+//
+fn code(value: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = value.bytes().take(3).collect();
+    bytes.resize(3, b' ');
+    bytes
+}
+
+//
+// Bytes of a frame that has strings in its encoding, without a frame header.
+// rtag writes them in UTF-8 whatever the encoding is. None for other frames. see `encodes`
+//
+pub fn encode_body(fbody: &FrameBody) -> Option<Vec<u8>> {
+    let encoding = encoding(fbody)?;
+    let mut bytes = vec![types::from_encoding(encoding)];
+
+    match *fbody {
+        FrameBody::COMM(ref f) => {
+            bytes.extend(code(&f.language));
+            bytes.extend(encode(encoding, &f.short_description, true));
+            bytes.extend(encode(encoding, &f.actual_text, false));
+        }
+        FrameBody::USLT(ref f) => {
+            bytes.extend(code(&f.language));
+            bytes.extend(encode(encoding, &f.content_descriptor, true));
+            bytes.extend(encode(encoding, &f.lyrics, false));
+        }
+        FrameBody::USER(ref f) => {
+            bytes.extend(code(&f.language));
+            bytes.extend(encode(encoding, &f.actual_text, false));
+        }
+        FrameBody::IPLS(ref f) => {
+            bytes.extend(encode(encoding, &f.people_list_strings, false));
+        }
+        FrameBody::GEOB(ref f) => {
+            bytes.extend(encode(&TextEncoding::ISO88591, &f.mime_type, true));
+            bytes.extend(encode(encoding, &f.filename, true));
+            bytes.extend(encode(encoding, &f.content_description, true));
+            bytes.extend_from_slice(&f.encapsulation_object);
+        }
+        FrameBody::TXXX(ref f) => {
+            bytes.extend(encode(encoding, &f.description, true));
+            bytes.extend(encode(encoding, &f.value, false));
+        }
+        FrameBody::WXXX(ref f) => {
+            bytes.extend(encode(encoding, &f.description, true));
+            bytes.extend(encode(&TextEncoding::ISO88591, &f.url, false));
+        }
+        FrameBody::APIC(ref f) => {
+            bytes.extend(encode(&TextEncoding::ISO88591, &f.mime_type, true));
+            bytes.push(types::from_picture_type(&f.picture_type));
+            bytes.extend(encode(encoding, &f.description, true));
+            bytes.extend_from_slice(&f.picture_data);
+        }
+        FrameBody::PIC(ref f) => {
+            bytes.extend(code(&f.image_format));
+            bytes.push(types::from_picture_type(&f.picture_type));
+            bytes.extend(encode(encoding, &f.description, true));
+            bytes.extend_from_slice(&f.picture_data);
+        }
+        _ => return None,
+    }
+
+    Some(bytes)
+}
+
+//
+// A frame of `data` like rtag reads it, and with strings decoded by `decode_strings`.
+//
+fn read_body(id: &str, version: u8, data: &[u8]) -> Option<(FrameBody, FrameBody)> {
+    let read = read_framebody_with_id(id, version, Cursor::new(data.to_vec())).ok()?;
+    let mut decoded = read.clone();
+    decode_strings(&mut decoded, data);
+
+    Some((read, decoded))
+}
+
+//
+// Frames of strings but text frames, of version 3 and 4, and of version 2.
+//
+const STRING_FRAMES: [&str; 13] = ["COMM", "USLT", "USER", "IPLS", "GEOB", "WXXX", "APIC", "COM",
+                                   "ULT", "IPL", "GEO", "WXX", "PIC"];
+
+//
+// Frames of a tag that rtag reads wrong, and the right ones. see `decode_strings`
+//
+pub struct Strings(Vec<(FrameBody, FrameBody)>);
+
+impl Strings {
+    pub fn new(bytes: &[u8]) -> Strings {
+        let version = bytes.get(3).cloned().unwrap_or(4);
+
+        let frames = audio::id3v2_frames(bytes)
+            .into_iter()
+            .filter(|(id, _, _)| id.starts_with('T') || STRING_FRAMES.contains(&id.as_str()))
+            .filter_map(|(id, _, data)| read_body(&id, version, data))
+            .filter(|(read, decoded)| read != decoded)
+            .collect();
+
+        Strings(frames)
+    }
+
+    //
+    // Only a tag is read, not a whole file.
+    //
+    pub fn read(file: &Path) -> Strings {
+        let mut bytes = vec![0u8; 10];
+
+        let read = File::open(file).and_then(|mut f| {
+            f.read_exact(&mut bytes)?;
+            let size = audio::syncsafe_len(&bytes[6..10]);
+            bytes.resize(10 + size, 0);
+            f.read_exact(&mut bytes[10..])
+        });
+
+        match read {
+            Ok(_) if &bytes[..3] == b"ID3" => Strings::new(&bytes),
+            _ => Strings(Vec::new()),
+        }
+    }
+
+    //
+    // A frame of the right strings, if it is read wrong.
+    //
+    pub fn fix(&mut self, fbody: FrameBody) -> FrameBody {
+        match self.0.iter().position(|(read, _)| *read == fbody) {
+            Some(idx) => self.0.remove(idx).1,
+            None => fbody,
+        }
+    }
+}
+
+//
+// Strings of a frame in its encoding.
+//
+pub fn strings(fbody: &FrameBody) -> Vec<&str> {
+    match *fbody {
+        FrameBody::COMM(ref f) => vec![&f.short_description, &f.actual_text],
+        FrameBody::USLT(ref f) => vec![&f.content_descriptor, &f.lyrics],
+        FrameBody::USER(ref f) => vec![&f.actual_text],
+        FrameBody::IPLS(ref f) => vec![&f.people_list_strings],
+        FrameBody::GEOB(ref f) => vec![&f.filename, &f.content_description],
+        FrameBody::TXXX(ref f) => vec![&f.description, &f.value],
+        FrameBody::WXXX(ref f) => vec![&f.description],
+        FrameBody::APIC(ref f) => vec![&f.description],
+        FrameBody::PIC(ref f) => vec![&f.description],
+        _ => text_body(fbody).map(|f| vec![f.text.as_str()]).unwrap_or_default(),
+    }
+}
+
+//
+// Whether strings of a frame are written in its encoding.
+// rtag writes text frames right, and `encode_body` writes others but COMR, OWNE and SYLT.
+//
+pub fn encodes(fbody: &FrameBody) -> bool {
+    match *fbody {
+        FrameBody::COMM(_) | FrameBody::USLT(_) | FrameBody::USER(_) | FrameBody::IPLS(_) |
+        FrameBody::GEOB(_) | FrameBody::TXXX(_) | FrameBody::WXXX(_) | FrameBody::APIC(_) |
+        FrameBody::PIC(_) => true,
+        _ => text_body(fbody).is_some(),
+    }
+}
+
+//
+// An encoding of a frame for version 2 and 3 that have no UTF-8.
+// strings of COMR, OWNE and SYLT are written in UTF-8 bytes by rtag whatever the encoding is,
+// so they are ISO-8859-1 and can be read back by rtag only. see `encodes`
+//
+pub fn legacy_encoding(fbody: &FrameBody) -> TextEncoding {
+    if encodes(fbody) {
+        TextEncoding::UTF16LE
+    } else {
+        TextEncoding::ISO88591
+    }
+}
+
+//
+// Strings of COMR, OWNE and SYLT are written in UTF-8 bytes for ISO-8859-1,
+// so they are right only if they are ASCII. see `legacy_encoding`
+//
+pub fn is_ascii(fbody: &FrameBody) -> bool {
+    match serde_json::to_string(fbody) {
+        Ok(json) => json.is_ascii(),
+        Err(_) => true,
+    }
+}
+
+//
+// Frames that a song should have. TDRC is also TYER of version 3.
+//
+pub const ESSENTIALS: [&str; 7] = ["TIT2", "TPE1", "TALB", "TDRC", "TRCK", "TCON", "APIC"];
+
+//
+// Essential frames that are missing or empty.
+//
+pub fn missing_essentials(fbodies: &[&FrameBody]) -> Vec<&'static str> {
+    let present: Vec<String> = fbodies.iter()
+        .filter(|fbody| text_of(fbody).is_none_or(|(_, text)| !text.trim().is_empty()))
+        .map(|fbody| match framebody_to_id(fbody, 4) {
+            "TYER" => "TDRC".to_string(),
+            "PIC" => "APIC".to_string(),
+            id => id.to_string(),
+        })
+        .collect();
+
+    ESSENTIALS.iter().filter(|id| !present.iter().any(|p| p == *id)).cloned().collect()
+}
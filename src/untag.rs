@@ -0,0 +1,271 @@
+use audio;
+use clap;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const TAGS: [&str; 4] = ["ID3v2", "ID3v1", "APEv2", "Lyrics3"];
+
+//
+// "id3v2,apev2" or "all" => names of `TAGS`
+//
+fn tags(value: &str) -> Option<Vec<&'static str>> {
+    let mut tags = Vec::new();
+
+    for name in value.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+        if name.eq_ignore_ascii_case("all") {
+            return Some(TAGS.to_vec());
+        }
+
+        match TAGS.iter().find(|tag| tag.eq_ignore_ascii_case(name)) {
+            Some(tag) => tags.push(*tag),
+            None => {
+                error!("Unknown tag: {}. (id3v2|id3v1|apev2|lyrics3|all)", name);
+                return None;
+            }
+        }
+    }
+
+    Some(tags)
+}
+
+//
+// Bytes without the tags, and (name, bytes) of removed ones. the audio is copied as is.
+//
+fn remove(bytes: &[u8], tags: &[&str]) -> (Vec<u8>, Vec<(&'static str, usize)>) {
+    let layout = audio::layout(bytes);
+    let (start, end) = audio::payload(bytes);
+    let mut removed = Vec::new();
+
+    let mut out = Vec::with_capacity(bytes.len());
+
+    if layout.id3v2.is_some() && tags.contains(&"ID3v2") {
+        removed.push(("ID3v2", start));
+    } else {
+        out.extend_from_slice(&bytes[..start]);
+    }
+
+    out.extend_from_slice(&bytes[start..end]);
+
+    //
+    // tags at the end are kept in order.
+    //
+    for &(name, tag_start, len) in layout.trailing.iter().rev() {
+        if tags.contains(&name) {
+            removed.push((name, len));
+        } else {
+            out.extend_from_slice(&bytes[tag_start..tag_start + len]);
+        }
+    }
+
+    (out, removed)
+}
+
+//
+// It writes a file next to a file and renames it, not to break a file on a failure.
+// a target of a symbolic link is replaced, and permissions and an owner are kept if they can be.
+//
+pub fn replace(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let path = path.canonicalize()?;
+    let metadata = fs::metadata(&path)?;
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".markdang");
+
+    if let Err(e) = fs::write(&temp, bytes) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    let kept = fs::set_permissions(&temp, metadata.permissions())
+        .and_then(|_| chown(Path::new(&temp), &metadata));
+
+    if let Err(e) = kept {
+        warn!("Permissions or an owner are not kept: {:?}, {:?}", path, e);
+    }
+
+    fs::rename(&temp, &path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+#[cfg(unix)]
+fn chown(path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    ::std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn chown(_: &Path, _: &fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn remove_tags(matches: clap::ArgMatches) {
+    let files: Vec<_> = matches.values_of("INPUT").unwrap().collect();
+    let dry_run = matches.is_present("dry-run");
+
+    let tags = match tags(matches.value_of("remove-tags").unwrap()) {
+        Some(tags) => tags,
+        None => return,
+    };
+
+    for file in files {
+        let path = match PathBuf::from(file).canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("{}: {:?}", file, e);
+                continue;
+            }
+        };
+
+        let bytes = match audio::read(&path) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+
+        let (out, removed) = remove(&bytes, &tags);
+
+        if removed.is_empty() {
+            debug!("no tag to remove: {:?}", path);
+            continue;
+        }
+
+        println!("{}", path.display());
+        for (name, len) in removed {
+            println!("\t- {} ({} bytes)", name, len);
+        }
+
+        if dry_run {
+            continue;
+        }
+
+        if let Err(e) = replace(&path, &out) {
+            error!("Can not write: {:?}, {:?}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id3v2() -> Vec<u8> {
+        let mut bytes = b"ID3\x03\x00\x00\x00\x00\x00\x1a".to_vec();
+        bytes.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x00Title");
+        bytes.extend_from_slice(&[0; 10]);
+        bytes
+    }
+
+    fn id3v1() -> Vec<u8> {
+        let mut bytes = b"TAG".to_vec();
+        bytes.resize(128, b' ');
+        bytes
+    }
+
+    //
+    // 32 bytes of a header or a footer. `size` is of items and a footer.
+    //
+    fn ape_block(size: usize, header: bool, is_header: bool) -> Vec<u8> {
+        let mut flags = 0u32;
+        if header {
+            flags |= 0x8000_0000;
+        }
+        if is_header {
+            flags |= 0x2000_0000;
+        }
+
+        let mut bytes = b"APETAGEX".to_vec();
+        bytes.extend_from_slice(&2000u32.to_le_bytes());
+        bytes.extend_from_slice(&(size as u32).to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&flags.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        bytes
+    }
+
+    fn apev2(header: bool) -> Vec<u8> {
+        let items = b"\x05\x00\x00\x00\x00\x00\x00\x00Title\x00Hello".to_vec();
+        let size = items.len() + 32;
+
+        let mut bytes = if header { ape_block(size, true, true) } else { Vec::new() };
+        bytes.extend(items);
+        bytes.extend(ape_block(size, header, false));
+        bytes
+    }
+
+    fn lyrics3_v1() -> Vec<u8> {
+        b"LYRICSBEGINla la la\r\nLYRICSEND".to_vec()
+    }
+
+    fn lyrics3_v2() -> Vec<u8> {
+        let mut bytes = b"LYRICSBEGININD00002".to_vec();
+        bytes.extend_from_slice(b"11");
+        bytes.extend_from_slice(b"LYR00005la la");
+        bytes.extend_from_slice(format!("{:06}", bytes.len()).as_bytes());
+        bytes.extend_from_slice(b"LYRICS200");
+        bytes
+    }
+
+    fn audio() -> Vec<u8> {
+        let mut bytes = vec![0xff, 0xfb, 0x90, 0x00];
+        bytes.resize(417, 0x55);
+        bytes
+    }
+
+    #[test]
+    fn names_of_tags() {
+        assert_eq!(tags("id3v2, APEv2"), Some(vec!["ID3v2", "APEv2"]));
+        assert_eq!(tags("lyrics3,all"), Some(TAGS.to_vec()));
+        assert_eq!(tags(""), Some(vec![]));
+        assert_eq!(tags("id3v2,ogg"), None);
+    }
+
+    #[test]
+    fn remove_id3() {
+        let bytes = [id3v2(), audio(), id3v1()].concat();
+
+        assert_eq!(remove(&bytes, &["ID3v1"]),
+                   ([id3v2(), audio()].concat(), vec![("ID3v1", 128)]));
+        assert_eq!(remove(&bytes, &["ID3v2"]),
+                   ([audio(), id3v1()].concat(), vec![("ID3v2", id3v2().len())]));
+        assert_eq!(remove(&bytes, &["APEv2", "Lyrics3"]), (bytes.clone(), vec![]));
+        assert_eq!(remove(&audio(), &TAGS), (audio(), vec![]));
+    }
+
+    #[test]
+    fn remove_apev2() {
+        for &header in &[true, false] {
+            let ape = apev2(header);
+            let bytes = [audio(), ape.clone(), id3v1()].concat();
+
+            assert_eq!(remove(&bytes, &["APEv2"]),
+                       ([audio(), id3v1()].concat(), vec![("APEv2", ape.len())]));
+            assert_eq!(remove(&bytes, &["ID3v1"]),
+                       ([audio(), ape.clone()].concat(), vec![("ID3v1", 128)]));
+        }
+    }
+
+    #[test]
+    fn remove_lyrics3() {
+        for lyrics in &[lyrics3_v1(), lyrics3_v2()] {
+            let bytes = [audio(), lyrics.clone(), id3v1()].concat();
+
+            assert_eq!(remove(&bytes, &["Lyrics3"]),
+                       ([audio(), id3v1()].concat(), vec![("Lyrics3", lyrics.len())]));
+        }
+    }
+
+    #[test]
+    fn remove_all() {
+        let bytes = [id3v2(), audio(), lyrics3_v2(), apev2(true), id3v1()].concat();
+
+        assert_eq!(remove(&bytes, &TAGS),
+                   (audio(),
+                    vec![("ID3v2", id3v2().len()),
+                         ("Lyrics3", lyrics3_v2().len()),
+                         ("APEv2", apev2(true).len()),
+                         ("ID3v1", 128)]));
+    }
+}